@@ -0,0 +1,28 @@
+-- Add migration script here
+-- the old columns were written by CURRENT_TIMESTAMP on a UTC server.
+ALTER TABLE users
+    ALTER COLUMN create_at TYPE TIMESTAMPTZ USING create_at AT TIME ZONE 'UTC',
+    ALTER COLUMN create_at SET DEFAULT CURRENT_TIMESTAMP,
+    ALTER COLUMN create_at SET NOT NULL,
+    ALTER COLUMN update_at TYPE TIMESTAMPTZ USING update_at AT TIME ZONE 'UTC',
+    ALTER COLUMN update_at SET DEFAULT CURRENT_TIMESTAMP,
+    ALTER COLUMN update_at SET NOT NULL;
+
+ALTER TABLE sessions
+    ALTER COLUMN created_at TYPE TIMESTAMPTZ USING created_at AT TIME ZONE 'UTC',
+    ALTER COLUMN created_at SET DEFAULT CURRENT_TIMESTAMP,
+    ALTER COLUMN expires_at TYPE TIMESTAMPTZ USING expires_at AT TIME ZONE 'UTC',
+    ALTER COLUMN expires_at SET DEFAULT CURRENT_TIMESTAMP + INTERVAL '7 days';
+
+ALTER TABLE conversations
+    ALTER COLUMN created_at TYPE TIMESTAMPTZ USING created_at AT TIME ZONE 'UTC',
+    ALTER COLUMN created_at SET DEFAULT CURRENT_TIMESTAMP,
+    ALTER COLUMN created_at SET NOT NULL,
+    ALTER COLUMN updated_at TYPE TIMESTAMPTZ USING updated_at AT TIME ZONE 'UTC',
+    ALTER COLUMN updated_at SET DEFAULT CURRENT_TIMESTAMP,
+    ALTER COLUMN updated_at SET NOT NULL;
+
+ALTER TABLE messages
+    ALTER COLUMN created_at TYPE TIMESTAMPTZ USING created_at AT TIME ZONE 'UTC',
+    ALTER COLUMN created_at SET DEFAULT CURRENT_TIMESTAMP,
+    ALTER COLUMN created_at SET NOT NULL;
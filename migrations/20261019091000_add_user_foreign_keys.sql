@@ -0,0 +1,31 @@
+-- Add migration script here
+-- conversations: keep the chat for the remaining participant when an account is deleted.
+DELETE FROM conversations
+WHERE
+    user1_id NOT IN (SELECT id FROM users) AND
+    user2_id NOT IN (SELECT id FROM users);
+
+ALTER TABLE conversations
+    ALTER COLUMN user1_id DROP NOT NULL,
+    ALTER COLUMN user2_id DROP NOT NULL;
+
+UPDATE conversations SET user1_id = NULL WHERE user1_id NOT IN (SELECT id FROM users);
+UPDATE conversations SET user2_id = NULL WHERE user2_id NOT IN (SELECT id FROM users);
+
+ALTER TABLE conversations
+    ADD FOREIGN KEY (user1_id) REFERENCES users(id) ON DELETE SET NULL,
+    ADD FOREIGN KEY (user2_id) REFERENCES users(id) ON DELETE SET NULL,
+    ADD CHECK (user1_id IS NOT NULL OR user2_id IS NOT NULL);
+
+-- messages: reference the sender by id, the receiver is the other participant.
+ALTER TABLE messages ADD COLUMN sender_id INT NULL;
+
+UPDATE messages
+SET sender_id = users.id
+FROM users
+WHERE users.username = messages.sender_username;
+
+ALTER TABLE messages
+    ADD FOREIGN KEY (sender_id) REFERENCES users(id) ON DELETE SET NULL,
+    DROP COLUMN sender_username,
+    DROP COLUMN receiver_username;
@@ -0,0 +1,40 @@
+-- Add migration script here
+-- merge the duplicated chats between the same pair into the oldest one.
+WITH ranked AS (
+    SELECT
+        id,
+        FIRST_VALUE(id) OVER (
+            PARTITION BY LEAST(user1_id, user2_id), GREATEST(user1_id, user2_id)
+            ORDER BY id
+        ) AS keep_id
+    FROM conversations
+    WHERE
+        user1_id IS NOT NULL AND
+        user2_id IS NOT NULL
+)
+UPDATE messages
+SET conversation_id = ranked.keep_id
+FROM ranked
+WHERE
+    messages.conversation_id = ranked.id AND
+    ranked.id <> ranked.keep_id;
+
+DELETE FROM conversations
+WHERE
+    user1_id IS NOT NULL AND
+    user2_id IS NOT NULL AND
+    id NOT IN (
+        SELECT MIN(id)
+        FROM conversations
+        WHERE
+            user1_id IS NOT NULL AND
+            user2_id IS NOT NULL
+        GROUP BY LEAST(user1_id, user2_id), GREATEST(user1_id, user2_id)
+    );
+
+-- A->B and B->A are the same chat, ignore the pairs with a deleted user.
+CREATE UNIQUE INDEX conversations_user_pair_key
+ON conversations (LEAST(user1_id, user2_id), GREATEST(user1_id, user2_id))
+WHERE
+    user1_id IS NOT NULL AND
+    user2_id IS NOT NULL;
@@ -0,0 +1,3 @@
+-- Add migration script here
+CREATE INDEX IF NOT EXISTS messages_conversation_id_idx
+ON messages (conversation_id, id);
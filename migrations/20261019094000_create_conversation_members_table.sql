@@ -0,0 +1,51 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS conversation_members (
+    conversation_id INT NOT NULL,
+    user_id INT NOT NULL,
+    last_delivered_message_id INT NULL,
+    last_read_message_id INT NULL,
+    joined_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (conversation_id, user_id),
+    FOREIGN KEY (conversation_id) REFERENCES conversations(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (last_delivered_message_id) REFERENCES messages(id) ON DELETE SET NULL,
+    FOREIGN KEY (last_read_message_id) REFERENCES messages(id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS conversation_members_user_id_idx
+ON conversation_members (user_id);
+
+-- the cursor of a member is the newest message the other side marked.
+INSERT INTO conversation_members (
+    conversation_id,
+    user_id,
+    last_delivered_message_id,
+    last_read_message_id
+)
+SELECT
+    members.conversation_id,
+    members.user_id,
+    (
+        SELECT MAX(id) FROM messages
+        WHERE
+            messages.conversation_id = members.conversation_id AND
+            (messages.delivered OR messages.sender_id = members.user_id)
+    ),
+    (
+        SELECT MAX(id) FROM messages
+        WHERE
+            messages.conversation_id = members.conversation_id AND
+            (messages.readed OR messages.sender_id = members.user_id)
+    )
+FROM (
+    SELECT id as conversation_id, user1_id as user_id
+    FROM conversations WHERE user1_id IS NOT NULL
+    UNION
+    SELECT id as conversation_id, user2_id as user_id
+    FROM conversations WHERE user2_id IS NOT NULL
+) AS members
+ON CONFLICT DO NOTHING;
+
+ALTER TABLE messages
+    DROP COLUMN delivered,
+    DROP COLUMN readed;
@@ -0,0 +1,14 @@
+-- Add migration script here
+ALTER TABLE messages ADD COLUMN edited_at TIMESTAMPTZ NULL;
+
+CREATE TABLE IF NOT EXISTS message_revisions (
+    id SERIAL PRIMARY KEY,
+    message_id INT NOT NULL,
+    body TEXT NOT NULL,
+    -- when this body was replaced by the next revision.
+    replaced_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (message_id) REFERENCES messages(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS message_revisions_message_id_idx
+ON message_revisions (message_id);
@@ -0,0 +1,13 @@
+-- Add migration script here
+-- deleted for everyone: the body is wiped and the message stays as a tombstone.
+ALTER TABLE messages ADD COLUMN deleted_at TIMESTAMPTZ NULL;
+
+-- deleted for me: the message is only hidden from this user.
+CREATE TABLE IF NOT EXISTS hidden_messages (
+    message_id INT NOT NULL,
+    user_id INT NOT NULL,
+    hidden_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (message_id, user_id),
+    FOREIGN KEY (message_id) REFERENCES messages(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
@@ -0,0 +1,4 @@
+-- Add migration script here
+ALTER TABLE messages
+    ADD COLUMN reply_to_message_id INT NULL,
+    ADD FOREIGN KEY (reply_to_message_id) REFERENCES messages(id) ON DELETE SET NULL;
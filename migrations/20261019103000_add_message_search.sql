@@ -0,0 +1,8 @@
+-- Add migration script here
+-- 'simple' does not stem, our users do not write in a single language.
+ALTER TABLE messages
+    ADD COLUMN body_tsv TSVECTOR
+    GENERATED ALWAYS AS (to_tsvector('simple', body)) STORED;
+
+CREATE INDEX IF NOT EXISTS messages_body_tsv_idx
+ON messages USING GIN (body_tsv);
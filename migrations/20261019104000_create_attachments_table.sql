@@ -0,0 +1,24 @@
+-- Add migration script here
+-- an attachment is uploaded to a conversation first and
+-- linked to the message when the message is sent.
+CREATE TABLE IF NOT EXISTS attachments (
+    id SERIAL PRIMARY KEY,
+    conversation_id INT NOT NULL,
+    message_id INT NULL,
+    uploader_id INT NULL,
+    storage_key VARCHAR(255) UNIQUE NOT NULL,
+    filename VARCHAR(255) NOT NULL,
+    mime_type VARCHAR(255) NOT NULL,
+    size BIGINT NOT NULL,
+    checksum CHAR(64) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (conversation_id) REFERENCES conversations(id) ON DELETE CASCADE,
+    FOREIGN KEY (message_id) REFERENCES messages(id) ON DELETE CASCADE,
+    FOREIGN KEY (uploader_id) REFERENCES users(id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS attachments_message_id_idx
+ON attachments (message_id);
+
+CREATE INDEX IF NOT EXISTS attachments_uploader_id_idx
+ON attachments (uploader_id);
@@ -0,0 +1,7 @@
+-- Add migration script here
+-- set only for the images, the thumbnail is stored next to the file.
+ALTER TABLE attachments
+ADD COLUMN IF NOT EXISTS width INT NULL,
+ADD COLUMN IF NOT EXISTS height INT NULL,
+ADD COLUMN IF NOT EXISTS blurhash VARCHAR(64) NULL,
+ADD COLUMN IF NOT EXISTS thumbnail_key VARCHAR(255) UNIQUE NULL;
@@ -0,0 +1,10 @@
+-- Add migration script here
+-- the resumable uploads are sent to the server in chunks,
+-- every chunk is stored on its own until the upload is finished.
+ALTER TABLE uploads
+ADD COLUMN IF NOT EXISTS resumable BOOLEAN NOT NULL DEFAULT FALSE,
+ADD COLUMN IF NOT EXISTS upload_offset BIGINT NOT NULL DEFAULT 0,
+ADD COLUMN IF NOT EXISTS part_keys TEXT[] NOT NULL DEFAULT '{}';
+
+CREATE INDEX IF NOT EXISTS uploads_expires_at_idx
+ON uploads (expires_at);
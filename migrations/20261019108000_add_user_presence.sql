@@ -0,0 +1,12 @@
+-- Add migration script here
+-- who can see a setting of the user, `contacts` are the users
+-- with an accepted contact request from or to the user.
+DO $$ BEGIN
+    CREATE TYPE visibility AS ENUM ('everyone', 'contacts', 'nobody');
+EXCEPTION
+    WHEN duplicate_object THEN NULL;
+END $$;
+
+ALTER TABLE users
+ADD COLUMN IF NOT EXISTS last_seen_at TIMESTAMPTZ NULL,
+ADD COLUMN IF NOT EXISTS last_seen_visibility visibility NOT NULL DEFAULT 'everyone';
@@ -0,0 +1,5 @@
+-- Add migration script here
+-- a pending conversation is a message request from user1_id to user2_id,
+-- it stays out of the recipient's conversation list until it is accepted.
+ALTER TABLE conversations
+ADD COLUMN IF NOT EXISTS pending BOOLEAN NOT NULL DEFAULT FALSE;
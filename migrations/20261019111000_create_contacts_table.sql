@@ -0,0 +1,20 @@
+-- Add migration script here
+-- a contact request from requester_id to addressee_id,
+-- the two users are contacts once it is accepted.
+CREATE TABLE IF NOT EXISTS contacts (
+    requester_id INT NOT NULL,
+    addressee_id INT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    accepted_at TIMESTAMPTZ NULL,
+    PRIMARY KEY (requester_id, addressee_id),
+    CHECK (requester_id <> addressee_id),
+    FOREIGN KEY (requester_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (addressee_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+-- one request or contact for each pair of users.
+CREATE UNIQUE INDEX IF NOT EXISTS contacts_user_pair_key
+ON contacts (LEAST(requester_id, addressee_id), GREATEST(requester_id, addressee_id));
+
+CREATE INDEX IF NOT EXISTS contacts_addressee_id_idx
+ON contacts (addressee_id);
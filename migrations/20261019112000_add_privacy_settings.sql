@@ -0,0 +1,8 @@
+-- Add migration script here
+-- who can see the email, who can start a conversation with the user,
+-- whether the user can be looked up by strangers and shares the read receipts.
+ALTER TABLE users
+ADD COLUMN IF NOT EXISTS email_visibility visibility NOT NULL DEFAULT 'contacts',
+ADD COLUMN IF NOT EXISTS conversation_visibility visibility NOT NULL DEFAULT 'everyone',
+ADD COLUMN IF NOT EXISTS discoverable BOOLEAN NOT NULL DEFAULT TRUE,
+ADD COLUMN IF NOT EXISTS read_receipts BOOLEAN NOT NULL DEFAULT TRUE;
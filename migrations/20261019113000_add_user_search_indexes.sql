@@ -0,0 +1,9 @@
+-- Add migration script here
+-- the user search matches the prefix or the trigrams of the username and the name.
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE INDEX IF NOT EXISTS users_username_trgm_idx
+ON users USING GIN (lower(username) gin_trgm_ops);
+
+CREATE INDEX IF NOT EXISTS users_name_trgm_idx
+ON users USING GIN (lower(name) gin_trgm_ops);
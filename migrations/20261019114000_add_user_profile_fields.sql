@@ -0,0 +1,8 @@
+-- Add migration script here
+-- the avatar files are kept in the store as `{avatar_id}-{size}`.
+ALTER TABLE users
+ADD COLUMN IF NOT EXISTS avatar_id TEXT NULL,
+ADD COLUMN IF NOT EXISTS bio TEXT NULL,
+ADD COLUMN IF NOT EXISTS status_text TEXT NULL,
+ADD COLUMN IF NOT EXISTS status_expires_at TIMESTAMPTZ NULL,
+ADD COLUMN IF NOT EXISTS time_zone TEXT NULL;
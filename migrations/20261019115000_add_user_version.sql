@@ -0,0 +1,4 @@
+-- Add migration script here
+-- bumped by every edit of the profile, the clients send it back in `If-Match`.
+ALTER TABLE users
+ADD COLUMN IF NOT EXISTS version INT NOT NULL DEFAULT 1;
@@ -0,0 +1,4 @@
+-- Add migration script here
+-- who can see the gender of the user.
+ALTER TABLE users
+ADD COLUMN IF NOT EXISTS gender_visibility visibility NOT NULL DEFAULT 'contacts';
@@ -0,0 +1,116 @@
+use chrono::Duration;
+
+pub enum StorageBackend {
+    Fs {
+        dir: String,
+    },
+    S3 {
+        endpoint: String,
+        bucket: String,
+        region: String,
+        access_key: String,
+        secret_key: String,
+    },
+}
+
+pub struct Config {
+    pub port: String,
+    pub database_url: String,
+    pub mail_from: String,
+    // how long after sending a message the sender can still edit it.
+    pub message_edit_window: Duration,
+    // how long after sending a message the sender can still delete it for everyone.
+    pub message_delete_window: Duration,
+    // how many messages the initiator of a message request can send before it is accepted.
+    pub message_request_limit: i64,
+    // CHAT_STORAGE=fs (default) or CHAT_STORAGE=s3.
+    pub storage: StorageBackend,
+    // how long the presigned urls given to the clients are valid.
+    pub presigned_url_expires_in: std::time::Duration,
+    // how long a resumable upload is kept after its last chunk.
+    pub resumable_upload_expires_in: std::time::Duration,
+    // in bytes, the biggest single attachment.
+    pub max_attachment_size: i64,
+    // in bytes, the biggest avatar image before it is resized.
+    pub max_avatar_size: i64,
+    // in bytes, the total size of the attachments a user can upload.
+    pub user_storage_quota: i64,
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        let port = std::env::var("CHAT_APP_PORT")
+            .expect(">>> CHAT_APP_PORT NOT found!");
+        let database_url = std::env::var("CHAT_DATABASE_URL")
+            .expect(">>> CHAT_DATABASE_URL NOT found!");
+        let mail_from = std::env::var("CHAT_MAIL_FROM")
+            .unwrap_or("no-reply@chat.local".to_string());
+        let message_edit_window = Duration::seconds(
+            env_number("CHAT_MESSAGE_EDIT_WINDOW_SECONDS", 15 * 60)
+        );
+        let message_delete_window = Duration::seconds(
+            env_number("CHAT_MESSAGE_DELETE_WINDOW_SECONDS", 60 * 60)
+        );
+        let message_request_limit = env_number("CHAT_MESSAGE_REQUEST_LIMIT", 3);
+        let storage = match std::env::var("CHAT_STORAGE").as_deref() {
+            Ok("s3") => StorageBackend::S3 {
+                endpoint: std::env::var("CHAT_S3_ENDPOINT")
+                    .expect(">>> CHAT_S3_ENDPOINT NOT found!"),
+                bucket: std::env::var("CHAT_S3_BUCKET")
+                    .expect(">>> CHAT_S3_BUCKET NOT found!"),
+                region: std::env::var("CHAT_S3_REGION")
+                    .unwrap_or("us-east-1".to_string()),
+                access_key: std::env::var("CHAT_S3_ACCESS_KEY")
+                    .expect(">>> CHAT_S3_ACCESS_KEY NOT found!"),
+                secret_key: std::env::var("CHAT_S3_SECRET_KEY")
+                    .expect(">>> CHAT_S3_SECRET_KEY NOT found!"),
+            },
+            Ok("fs") | Err(_) => StorageBackend::Fs {
+                dir: std::env::var("CHAT_STORAGE_DIR")
+                    .unwrap_or("./storage".to_string()),
+            },
+            Ok(other) => panic!(">>> CHAT_STORAGE '{}' is NOT supported!", other),
+        };
+        let presigned_url_expires_in = std::time::Duration::from_secs(
+            env_number("CHAT_PRESIGNED_URL_EXPIRES_SECONDS", 15 * 60) as u64
+        );
+        let resumable_upload_expires_in = std::time::Duration::from_secs(
+            env_number("CHAT_RESUMABLE_UPLOAD_EXPIRES_SECONDS", 24 * 60 * 60) as u64
+        );
+        let max_attachment_size = env_number(
+            "CHAT_MAX_ATTACHMENT_SIZE",
+            25 * 1024 * 1024
+        );
+        let max_avatar_size = env_number(
+            "CHAT_MAX_AVATAR_SIZE",
+            5 * 1024 * 1024
+        );
+        let user_storage_quota = env_number(
+            "CHAT_USER_STORAGE_QUOTA",
+            1024 * 1024 * 1024
+        );
+        return Self {
+            port,
+            database_url,
+            mail_from,
+            message_edit_window,
+            message_delete_window,
+            message_request_limit,
+            storage,
+            presigned_url_expires_in,
+            resumable_upload_expires_in,
+            max_attachment_size,
+            max_avatar_size,
+            user_storage_quota,
+        };
+    }
+}
+
+fn env_number(name: &str, default: i64) -> i64 {
+    match std::env::var(name) {
+        Ok(value) => return value
+            .parse()
+            .unwrap_or_else(|_| panic!(">>> {} must be a number!", name)),
+        Err(_) => return default
+    }
+}
@@ -4,12 +4,12 @@ use sqlx::{
     Postgres
 };
 
-pub async fn create_db_connection() -> Pool<Postgres> {
-    let db_url = std::env::var("CHAT_DATABASE_URL")
-        .expect(">>> CHAT_DATABASE_URL NOT found!");
+use crate::config::Config;
+
+pub async fn create_db_connection(config: &Config) -> Pool<Postgres> {
     return PgPoolOptions::new()
         .max_connections(10)
-        .connect(&db_url)
+        .connect(&config.database_url)
         .await
         .expect(">>> Can NOT connect to database!");
-}
\ No newline at end of file
+}
@@ -2,15 +2,21 @@ use axum::{http::StatusCode, response::IntoResponse, Json};
 use serde_json::json;
 
 
-#[derive(PartialEq)]
+#[derive(Debug, PartialEq)]
 pub enum AppError {
     ValidationError(String),
     UserFound,
     InternalServerError,
     Unauthorized,
+    Forbidden,
     NotFoundUser,
     BadRequest,
-    NotFoundData
+    NotFoundData,
+    PayloadTooLarge,
+    QuotaExceeded,
+    Conflict,
+    UnsupportedMediaType,
+    PreconditionFailed
 }
 
 impl IntoResponse for AppError {
@@ -20,9 +26,15 @@ impl IntoResponse for AppError {
             AppError::UserFound => (StatusCode::FOUND, "User found!".to_string()),
             AppError::InternalServerError => (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error".to_string()),
             AppError::Unauthorized => (StatusCode::UNAUTHORIZED, "Unauthorized".to_string()),
+            AppError::Forbidden => (StatusCode::FORBIDDEN, "Forbidden".to_string()),
             AppError::NotFoundUser => (StatusCode::NOT_FOUND, "User NOT found!".to_string()),
             AppError::BadRequest => (StatusCode::BAD_REQUEST, "Bad Request".to_string()),
-            AppError::NotFoundData => (StatusCode::NOT_FOUND, "Data NOT found!".to_string())
+            AppError::NotFoundData => (StatusCode::NOT_FOUND, "Data NOT found!".to_string()),
+            AppError::PayloadTooLarge => (StatusCode::PAYLOAD_TOO_LARGE, "Payload too large!".to_string()),
+            AppError::QuotaExceeded => (StatusCode::PAYLOAD_TOO_LARGE, "Storage quota exceeded!".to_string()),
+            AppError::Conflict => (StatusCode::CONFLICT, "Conflict".to_string()),
+            AppError::UnsupportedMediaType => (StatusCode::UNSUPPORTED_MEDIA_TYPE, "Unsupported Media Type".to_string()),
+            AppError::PreconditionFailed => (StatusCode::PRECONDITION_FAILED, "Precondition Failed".to_string())
         };
         let res = Json(json!({
             "message": message,
@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::modules::{
+    conversation::Conversation,
+    message::{Message, ReactionChange, ReadCursor},
+    user::PresenceView
+};
+
+
+#[derive(Serialize, Clone)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum EventPayload {
+    ConversationCreated(Conversation),
+    // sent to both users when the recipient accepts a message request.
+    ConversationAccepted(Conversation),
+    MessageCreated(Message),
+    MessageUpdated(Message),
+    MessageDeleted(Message),
+    // only sent to the other sessions of the user that hid the message.
+    MessageHidden { id: i32, conversation_id: i32 },
+    CursorUpdated(ReadCursor),
+    ReactionChanged(ReactionChange),
+    // ephemeral, nothing is stored. a `TypingStopped` always follows,
+    // sent by the client or by the server once the typing expires.
+    TypingStarted { conversation_id: i32, user_id: i32 },
+    TypingStopped { conversation_id: i32, user_id: i32 },
+    // sent to the peers when the first session of the user connects
+    // and when the last one disconnects.
+    PresenceChanged { user_id: i32, presence: PresenceView },
+    // sent to the other user, declined and cancelled requests are not announced.
+    ContactRequested { user_id: i32 },
+    ContactAccepted { user_id: i32 },
+    ContactRemoved { user_id: i32 },
+}
+
+// what the clients send over the WebSocket, same shape as `EventPayload`.
+#[derive(Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum ClientEvent {
+    TypingStarted { conversation_id: i32 },
+    TypingStopped { conversation_id: i32 },
+}
+
+#[derive(Clone)]
+pub struct Event {
+    // ids of the users this event should be delivered to.
+    pub recipients: Vec<i32>,
+    pub payload: EventPayload,
+}
+
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<Event>,
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        return Self { sender };
+    }
+
+    pub fn publish(&self, recipients: Vec<i32>, payload: EventPayload) {
+        // send only fails when nobody is subscribed, the event is dropped.
+        let _ = self.sender.send(Event { recipients, payload });
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        return self.sender.subscribe();
+    }
+}
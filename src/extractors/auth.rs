@@ -0,0 +1,41 @@
+use axum::{extract::FromRequestParts, http::request::Parts};
+use axum_extra::extract::CookieJar;
+
+use crate::{
+    error::AppError,
+    modules::user::User,
+    services,
+    state::AppState
+};
+
+
+// any handler that takes an `AuthUser` is authenticated,
+// the request is rejected with 401 if the session is missing or expired,
+// and counts as activity of the user for the presence.
+pub struct AuthUser(pub User);
+
+impl FromRequestParts<AppState> for AuthUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState
+    ) -> Result<Self, Self::Rejection> {
+        let jar = CookieJar::from_headers(&parts.headers);
+        let Some(session_id) = jar.get("session") else {
+            return Err(AppError::Unauthorized);
+        };
+        let get_user_result = services::session::get_user_by_session(
+            session_id.value().to_string(),
+            &state.pool
+        ).await;
+        match get_user_result {
+            Ok(user) => {
+                services::user::record_activity(&user, &state.pool).await;
+                return Ok(AuthUser(user));
+            }
+            Err(AppError::NotFoundUser) => return Err(AppError::Unauthorized),
+            Err(err) => return Err(err)
+        }
+    }
+}
@@ -0,0 +1,402 @@
+use axum::{
+    extract::{Multipart, Path, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+    Json
+};
+use chrono::{DateTime, Utc};
+use tracing::error;
+use validator::Validate;
+
+use crate::{
+    error::AppError,
+    extractors::auth::AuthUser,
+    modules::attachment::{PresignDto, PresignedUpload},
+    services,
+    state::AppState,
+    utils::{etag_matches, media, timestamp}
+};
+
+const MAX_FILES_PER_UPLOAD: usize = 10;
+
+
+// uploads the `file` fields of the multipart body as pending attachments,
+// send their ids with the message to attach them.
+pub async fn upload(
+    Path(conversation_id): Path<i32>,
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    mut multipart: Multipart
+) -> Response {
+    if let Err(err) = services::conversation::get_member_ids(
+        conversation_id,
+        user.id,
+        &state.pool
+    ).await {
+        return err.into_response();
+    }
+    let mut attachments = Vec::new();
+    loop {
+        let mut field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(err) => return AppError::ValidationError(err.body_text()).into_response()
+        };
+        if field.name() != Some("file") {
+            continue;
+        }
+        if attachments.len() >= MAX_FILES_PER_UPLOAD {
+            return AppError::ValidationError(
+                format!("file: max={}", MAX_FILES_PER_UPLOAD)
+            ).into_response();
+        }
+        let filename = sanitize_filename(field.file_name().unwrap_or("file"));
+        let mut data = Vec::new();
+        loop {
+            match field.chunk().await {
+                Ok(Some(chunk)) => {
+                    if (data.len() + chunk.len()) as i64 > state.config.max_attachment_size {
+                        return AppError::PayloadTooLarge.into_response();
+                    }
+                    data.extend_from_slice(&chunk);
+                }
+                Ok(None) => break,
+                Err(err) => return AppError::ValidationError(err.body_text()).into_response()
+            }
+        }
+        let create_result = services::attachment::create(
+            &user,
+            conversation_id,
+            filename,
+            data,
+            state.config.user_storage_quota,
+            state.storage.as_ref(),
+            &state.pool
+        ).await;
+        match create_result {
+            Ok(attachment) => attachments.push(attachment),
+            Err(err) => return err.into_response()
+        }
+    }
+    if attachments.is_empty() {
+        return AppError::ValidationError("file: required".to_string()).into_response();
+    }
+    return (
+        StatusCode::CREATED,
+        Json(attachments)
+    ).into_response();
+}
+
+// gives the client a url to upload the file straight to the storage,
+// only the object storage backends can do it.
+pub async fn presign(
+    Path(conversation_id): Path<i32>,
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Json(presign_dto): Json<PresignDto>
+) -> Response {
+    if let Err(err) = presign_dto.validate() {
+        return AppError::ValidationError(err.to_string()).into_response();
+    }
+    if presign_dto.size > state.config.max_attachment_size {
+        return AppError::PayloadTooLarge.into_response();
+    }
+    if let Err(err) = services::conversation::get_member_ids(
+        conversation_id,
+        user.id,
+        &state.pool
+    ).await {
+        return err.into_response();
+    }
+    let mut presign_dto = presign_dto;
+    presign_dto.filename = sanitize_filename(&presign_dto.filename);
+    // nothing is recorded when the store can not presign.
+    let storage_key = services::attachment::new_storage_key();
+    let Some(url) = state.storage.presign_put(
+        &storage_key,
+        presign_dto.size as u64,
+        state.config.presigned_url_expires_in
+    ) else {
+        return AppError::BadRequest.into_response();
+    };
+    let create_result = services::attachment::create_upload(
+        &user,
+        conversation_id,
+        storage_key,
+        presign_dto.filename,
+        presign_dto.size,
+        false,
+        state.config.presigned_url_expires_in,
+        state.config.user_storage_quota,
+        &state.pool
+    ).await;
+    let upload = match create_result {
+        Ok(upload) => upload,
+        Err(err) => return err.into_response()
+    };
+    return (
+        StatusCode::CREATED,
+        Json(PresignedUpload {
+            upload,
+            method: "PUT".to_string(),
+            url
+        })
+    ).into_response();
+}
+
+pub async fn complete(
+    Path(id): Path<i32>,
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>
+) -> Response {
+    let complete_result = services::attachment::complete_upload(
+        &user,
+        id,
+        state.config.max_attachment_size,
+        state.config.user_storage_quota,
+        state.storage.as_ref(),
+        &state.pool
+    ).await;
+    match complete_result {
+        Ok(attachment) => return (
+                StatusCode::CREATED,
+                Json(attachment)
+            ).into_response(),
+        Err(err) => return err.into_response()
+    }
+}
+
+// the players seek with `Range` and the caches revalidate with the `ETag`,
+// the object storage backends handle both themselves after the redirect.
+pub async fn download(
+    Path(id): Path<i32>,
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    headers: HeaderMap
+) -> Response {
+    let attachment = match services::attachment::find(&user, id, &state.pool).await {
+        Ok(attachment) => attachment,
+        Err(err) => return err.into_response()
+    };
+    let etag = format!("\"{}\"", attachment.checksum);
+    let validators = [
+        (header::ETAG, etag.clone()),
+        (header::LAST_MODIFIED, timestamp::http_date(&attachment.created_at)),
+        // the file never changes, but the access to it can be lost.
+        (header::CACHE_CONTROL, "private, no-cache".to_string()),
+    ];
+    if is_not_modified(&headers, &etag, &attachment.created_at) {
+        return (StatusCode::NOT_MODIFIED, validators).into_response();
+    }
+    let content_disposition = content_disposition(&attachment.filename, &attachment.mime_type);
+    if let Some(url) = state.storage.presign_get(
+        &attachment.storage_key,
+        &attachment.mime_type,
+        &content_disposition,
+        state.config.presigned_url_expires_in
+    ) {
+        return Redirect::temporary(&url).into_response();
+    }
+    let size = attachment.size as u64;
+    let (status, start, length) = match byte_range(&headers, size, &etag, &attachment.created_at) {
+        ByteRange::Full => (StatusCode::OK, 0, size),
+        ByteRange::Partial(start, end) => (StatusCode::PARTIAL_CONTENT, start, end - start + 1),
+        ByteRange::Unsatisfiable => return (
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [(header::CONTENT_RANGE, format!("bytes */{}", size))]
+            ).into_response()
+    };
+    let data = match state.storage.get_range(&attachment.storage_key, start, length).await {
+        Ok(data) => data,
+        Err(AppError::NotFoundData) => {
+            error!("The blob of the attachment '{}' is missing!", attachment.id);
+            return AppError::InternalServerError.into_response();
+        }
+        Err(err) => return err.into_response()
+    };
+    let mut res = (
+        status,
+        validators,
+        [
+            (header::CONTENT_TYPE, attachment.mime_type),
+            (header::CONTENT_DISPOSITION, content_disposition),
+            (header::ACCEPT_RANGES, "bytes".to_string()),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+        ],
+        data
+    ).into_response();
+    if status == StatusCode::PARTIAL_CONTENT {
+        res.headers_mut().insert(
+            header::CONTENT_RANGE,
+            HeaderValue::from_str(&format!("bytes {}-{}/{}", start, start + length - 1, size)).unwrap()
+        );
+    }
+    return res;
+}
+
+// the thumbnails never change once made, the clients can cache them for good.
+pub async fn thumbnail(
+    Path(id): Path<i32>,
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>
+) -> Response {
+    let attachment = match services::attachment::find(&user, id, &state.pool).await {
+        Ok(attachment) => attachment,
+        Err(err) => return err.into_response()
+    };
+    let Some(thumbnail_key) = attachment.thumbnail_key else {
+        return AppError::NotFoundData.into_response();
+    };
+    if let Some(url) = state.storage.presign_get(
+        &thumbnail_key,
+        media::THUMBNAIL_MIME_TYPE,
+        "inline",
+        state.config.presigned_url_expires_in
+    ) {
+        return Redirect::temporary(&url).into_response();
+    }
+    let data = match state.storage.get(&thumbnail_key).await {
+        Ok(data) => data,
+        Err(AppError::NotFoundData) => {
+            error!("The thumbnail of the attachment '{}' is missing!", attachment.id);
+            return AppError::InternalServerError.into_response();
+        }
+        Err(err) => return err.into_response()
+    };
+    return (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, media::THUMBNAIL_MIME_TYPE),
+            (header::CACHE_CONTROL, "private, max-age=31536000, immutable"),
+        ],
+        data
+    ).into_response();
+}
+
+// `If-None-Match` wins over `If-Modified-Since` when both are sent.
+fn is_not_modified(headers: &HeaderMap, etag: &str, last_modified: &DateTime<Utc>) -> bool {
+    if let Some(if_none_match) = headers.get(header::IF_NONE_MATCH) {
+        return match if_none_match.to_str() {
+            Ok(if_none_match) => etag_matches(if_none_match, etag),
+            Err(_) => false
+        };
+    }
+    match parse_http_date(headers.get(header::IF_MODIFIED_SINCE)) {
+        Some(since) => return last_modified.timestamp() <= since.timestamp(),
+        None => return false
+    }
+}
+
+fn parse_http_date(value: Option<&HeaderValue>) -> Option<DateTime<Utc>> {
+    let value = value?.to_str().ok()?;
+    return DateTime::parse_from_rfc2822(value)
+        .ok()
+        .map(|date| date.with_timezone(&Utc));
+}
+
+enum ByteRange {
+    Full,
+    // the first and the last byte, both included.
+    Partial(u64, u64),
+    Unsatisfiable,
+}
+
+// only a single range is served, the other requests get the whole file.
+fn byte_range(
+    headers: &HeaderMap,
+    size: u64,
+    etag: &str,
+    last_modified: &DateTime<Utc>
+) -> ByteRange {
+    let Some(range) = headers.get(header::RANGE).and_then(|value| value.to_str().ok()) else {
+        return ByteRange::Full;
+    };
+    // the range is for the version the client has, if it is still the same.
+    if let Some(if_range) = headers.get(header::IF_RANGE) {
+        let if_range_matches = match if_range.to_str() {
+            Ok(value) if value.starts_with('"') => value == etag,
+            Ok(_) => parse_http_date(Some(if_range))
+                .is_some_and(|date| date.timestamp() == last_modified.timestamp()),
+            Err(_) => false
+        };
+        if !if_range_matches {
+            return ByteRange::Full;
+        }
+    }
+    let Some(spec) = range.trim().strip_prefix("bytes=") else {
+        return ByteRange::Full;
+    };
+    if spec.contains(',') {
+        return ByteRange::Full;
+    }
+    let Some((first, last)) = spec.trim().split_once('-') else {
+        return ByteRange::Full;
+    };
+    let (first, last) = (first.trim(), last.trim());
+    if first.is_empty() {
+        // the last `n` bytes.
+        return match last.parse::<u64>() {
+            Ok(0) => ByteRange::Unsatisfiable,
+            Ok(_) if size == 0 => ByteRange::Unsatisfiable,
+            Ok(n) => ByteRange::Partial(size.saturating_sub(n), size - 1),
+            Err(_) => ByteRange::Full
+        };
+    }
+    let Ok(first) = first.parse::<u64>() else {
+        return ByteRange::Full;
+    };
+    let last = match last {
+        "" => size.saturating_sub(1),
+        last => match last.parse::<u64>() {
+            Ok(last) if last >= first => last.min(size.saturating_sub(1)),
+            _ => return ByteRange::Full
+        }
+    };
+    if first >= size {
+        return ByteRange::Unsatisfiable;
+    }
+    return ByteRange::Partial(first, last);
+}
+
+// the media is shown in place, the rest is saved,
+// `filename*` carries the name as is and `filename` is its ASCII fallback.
+fn content_disposition(filename: &str, mime_type: &str) -> String {
+    let disposition = if ["image/", "audio/", "video/"]
+        .iter()
+        .any(|prefix| mime_type.starts_with(prefix)) {
+        "inline"
+    } else {
+        "attachment"
+    };
+    let fallback = filename
+        .chars()
+        .map(|c| if c.is_ascii_graphic() && c != '\\' || c == ' ' { c } else { '_' })
+        .collect::<String>();
+    let encoded = filename
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' |
+            b'!' | b'#' | b'$' | b'&' | b'+' | b'-' | b'.' |
+            b'^' | b'_' | b'`' | b'|' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b)
+        })
+        .collect::<String>();
+    return format!("{}; filename=\"{}\"; filename*=UTF-8''{}", disposition, fallback, encoded);
+}
+
+// keeps only the last path component and drops the characters
+// that would break the Content-Disposition header.
+pub fn sanitize_filename(filename: &str) -> String {
+    let name = filename
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or("")
+        .chars()
+        .filter(|c| !c.is_control() && *c != '"')
+        .take(255)
+        .collect::<String>();
+    if name.is_empty() || name == "." || name == ".." {
+        return "file".to_string();
+    }
+    return name;
+}
@@ -0,0 +1,56 @@
+use axum::{extract::{Path, State}, http::StatusCode, response::{IntoResponse, Response}, Json};
+
+use crate::{extractors::auth::AuthUser, services, state::AppState};
+
+
+pub async fn create(
+    Path(username): Path<String>,
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>
+) -> Response {
+    let create_result = services::block::create(
+        &user,
+        username,
+        &state.pool
+    ).await;
+    match create_result {
+        Ok(blocked_user) => return (
+                StatusCode::OK,
+                Json(blocked_user)
+            ).into_response(),
+        Err(err) => return err.into_response()
+    }
+}
+
+pub async fn get_all(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>
+) -> Response {
+    let get_result = services::block::get_all(
+        &user,
+        &state.pool
+    ).await;
+    match get_result {
+        Ok(blocked_users) => return (
+                StatusCode::OK,
+                Json(blocked_users)
+            ).into_response(),
+        Err(err) => return err.into_response()
+    }
+}
+
+pub async fn delete(
+    Path(username): Path<String>,
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>
+) -> Response {
+    let delete_result = services::block::delete(
+        &user,
+        username,
+        &state.pool
+    ).await;
+    match delete_result {
+        Ok(_) => return (StatusCode::OK).into_response(),
+        Err(err) => return err.into_response()
+    }
+}
@@ -0,0 +1,152 @@
+use axum::{extract::{Path, State}, http::StatusCode, response::{IntoResponse, Response}, Json};
+use serde_json::json;
+
+use crate::{
+    events::EventPayload,
+    extractors::auth::AuthUser,
+    modules::contact::ContactStatus,
+    services,
+    state::AppState
+};
+
+
+pub async fn get_all(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>
+) -> Response {
+    let get_result = services::contact::get_all(
+        user.id,
+        &state.presence,
+        &state.pool
+    ).await;
+    match get_result {
+        Ok(contacts) => return (
+                StatusCode::OK,
+                Json(contacts)
+            ).into_response(),
+        Err(err) => return err.into_response()
+    }
+}
+
+pub async fn get_requests(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>
+) -> Response {
+    let get_result = services::contact::get_requests(
+        user.id,
+        &state.pool
+    ).await;
+    match get_result {
+        Ok(requests) => return (
+                StatusCode::OK,
+                Json(requests)
+            ).into_response(),
+        Err(err) => return err.into_response()
+    }
+}
+
+pub async fn send_request(
+    Path(username): Path<String>,
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>
+) -> Response {
+    let send_result = services::contact::send_request(
+        &user,
+        username,
+        &state.pool
+    ).await;
+    match send_result {
+        Ok((peer_id, status, changed)) => {
+            if changed {
+                let payload = match status {
+                    ContactStatus::Contact => EventPayload::ContactAccepted { user_id: user.id },
+                    _ => EventPayload::ContactRequested { user_id: user.id }
+                };
+                state.events.publish(vec![peer_id], payload);
+            }
+            return (
+                if changed { StatusCode::CREATED } else { StatusCode::OK },
+                Json(json!({ "status": status }))
+            ).into_response();
+        }
+        Err(err) => return err.into_response()
+    }
+}
+
+pub async fn accept(
+    Path(username): Path<String>,
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>
+) -> Response {
+    let accept_result = services::contact::accept(
+        &user,
+        username,
+        &state.pool
+    ).await;
+    match accept_result {
+        Ok(requester_id) => {
+            state.events.publish(
+                vec![requester_id],
+                EventPayload::ContactAccepted { user_id: user.id }
+            );
+            return (StatusCode::OK).into_response();
+        }
+        Err(err) => return err.into_response()
+    }
+}
+
+pub async fn decline(
+    Path(username): Path<String>,
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>
+) -> Response {
+    let delete_result = services::contact::delete_request(
+        &user,
+        username,
+        true,
+        &state.pool
+    ).await;
+    match delete_result {
+        Ok(_) => return (StatusCode::OK).into_response(),
+        Err(err) => return err.into_response()
+    }
+}
+
+pub async fn cancel(
+    Path(username): Path<String>,
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>
+) -> Response {
+    let delete_result = services::contact::delete_request(
+        &user,
+        username,
+        false,
+        &state.pool
+    ).await;
+    match delete_result {
+        Ok(_) => return (StatusCode::OK).into_response(),
+        Err(err) => return err.into_response()
+    }
+}
+
+pub async fn delete(
+    Path(username): Path<String>,
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>
+) -> Response {
+    let delete_result = services::contact::delete(
+        &user,
+        username,
+        &state.pool
+    ).await;
+    match delete_result {
+        Ok(contact_id) => {
+            state.events.publish(
+                vec![contact_id],
+                EventPayload::ContactRemoved { user_id: user.id }
+            );
+            return (StatusCode::OK).into_response();
+        }
+        Err(err) => return err.into_response()
+    }
+}
@@ -1,35 +1,51 @@
-use axum::{extract::Path, http::StatusCode, response::{IntoResponse, Response}, Extension, Json};
-use sqlx::{Pool, Postgres};
+use axum::{extract::{Path, Query, State}, http::StatusCode, response::{IntoResponse, Response}, Json};
 
-use crate::{modules::user::User, services};
+use crate::{events::EventPayload, extractors::auth::AuthUser, modules::conversation::ListQuery, services, state::AppState};
 
 
 pub async fn create(
     Path(username): Path<String>,
-    Extension(user): Extension<User>,
-    Extension(pool): Extension<Pool<Postgres>>
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>
 ) -> Response {
     let create_result = services::conversation::create(
         username, 
         user, 
-        &pool
+        &state.pool
     ).await;
     match create_result {
-        Ok(conversation) => return (
-                StatusCode::CREATED,
+        Ok((conversation, false)) => return (
+                StatusCode::OK,
                 Json(conversation)
             ).into_response(),
+        Ok((conversation, true)) => {
+            state.events.publish(
+                [conversation.user1_id, conversation.user2_id]
+                    .into_iter()
+                    .flatten()
+                    .collect(),
+                EventPayload::ConversationCreated(conversation.clone())
+            );
+            return (
+                StatusCode::CREATED,
+                Json(conversation)
+            ).into_response();
+        },
         Err(err) => return err.into_response()
     }
 }
 
 pub async fn get_all(
-    Extension(user): Extension<User>,
-    Extension(pool): Extension<Pool<Postgres>>
+    Query(list_query): Query<ListQuery>,
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>
 ) -> Response {
     let find_result = services::conversation::get_all(
         user.id, 
-        &pool
+        list_query,
+        false,
+        &state.presence,
+        &state.pool
     ).await;
     match find_result {
         Ok(conversations) => return (
@@ -42,16 +58,102 @@ pub async fn get_all(
 
 pub async fn delete(
     Path(id): Path<i32>,
-    Extension(user): Extension<User>,
-    Extension(pool): Extension<Pool<Postgres>>
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>
 ) -> Response {
     let delete_result = services::conversation::delete(
         id, 
         user.id, 
-        &pool
+        state.storage.as_ref(),
+        &state.pool
     ).await;
     match delete_result {
         Ok(_) => return (StatusCode::OK).into_response(),
         Err(err) => return err.into_response()
     }
-}
\ No newline at end of file
+}
+
+pub async fn get_requests(
+    Query(list_query): Query<ListQuery>,
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>
+) -> Response {
+    let find_result = services::conversation::get_all(
+        user.id,
+        list_query,
+        true,
+        &state.presence,
+        &state.pool
+    ).await;
+    match find_result {
+        Ok(conversations) => return (
+                StatusCode::OK,
+                Json(conversations)
+            ).into_response(),
+        Err(err) => return err.into_response()
+    }
+}
+
+pub async fn accept(
+    Path(id): Path<i32>,
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>
+) -> Response {
+    let accept_result = services::conversation::accept(
+        id,
+        user.id,
+        &state.pool
+    ).await;
+    match accept_result {
+        Ok(conversation) => {
+            state.events.publish(
+                [conversation.user1_id, conversation.user2_id]
+                    .into_iter()
+                    .flatten()
+                    .collect(),
+                EventPayload::ConversationAccepted(conversation.clone())
+            );
+            return (
+                StatusCode::OK,
+                Json(conversation)
+            ).into_response();
+        },
+        Err(err) => return err.into_response()
+    }
+}
+
+pub async fn decline(
+    Path(id): Path<i32>,
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>
+) -> Response {
+    return decline_request(id, user.id, false, state).await;
+}
+
+pub async fn block(
+    Path(id): Path<i32>,
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>
+) -> Response {
+    return decline_request(id, user.id, true, state).await;
+}
+
+// the initiator is not told, the request just disappears.
+async fn decline_request(
+    id: i32,
+    user_id: i32,
+    block: bool,
+    state: AppState
+) -> Response {
+    let decline_result = services::conversation::decline(
+        id,
+        user_id,
+        block,
+        state.storage.as_ref(),
+        &state.pool
+    ).await;
+    match decline_result {
+        Ok(_) => return (StatusCode::OK).into_response(),
+        Err(err) => return err.into_response()
+    }
+}
@@ -1,18 +1,38 @@
-use axum::{extract::Path, http::StatusCode, response::{IntoResponse, Response}, Extension, Json};
-use sqlx::{Pool, Postgres};
+use axum::{extract::{Path, Query, State}, http::StatusCode, response::{IntoResponse, Response}, Json};
+use validator::Validate;
 
-use crate::{modules::user::User, services};
+use crate::{
+    error::AppError,
+    events::EventPayload,
+    extractors::auth::AuthUser,
+    modules::{
+        message::{
+            emoji_validate,
+            normalize_emoji,
+            CursorDto,
+            DeleteQuery,
+            DeleteScope,
+            EditDto,
+            Message,
+            SearchQuery,
+            SendDto
+        },
+        user::User
+    },
+    services::{self, message::CursorKind},
+    state::AppState
+};
 
 
 pub async fn get_all(
     Path(conversation_id): Path<i32>,
-    Extension(user): Extension<User>,
-    Extension(pool): Extension<Pool<Postgres>>
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>
 ) -> Response {
     let get_result = services::message::get_all(
         user, 
         conversation_id, 
-        &pool
+        &state.pool
     ).await;
     match get_result {
         Ok(messages) => return (
@@ -21,4 +41,292 @@ pub async fn get_all(
             ).into_response(),
         Err(err) => return err.into_response()
     }
-}
\ No newline at end of file
+}
+
+pub async fn create(
+    Path(conversation_id): Path<i32>,
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Json(send_dto): Json<SendDto>
+) -> Response {
+    if let Err(err) = send_dto.validate() {
+        return AppError::ValidationError(err.to_string()).into_response();
+    }
+    let member_ids = match services::conversation::get_member_ids(
+        conversation_id,
+        user.id,
+        &state.pool
+    ).await {
+        Ok(member_ids) => member_ids,
+        Err(err) => return err.into_response()
+    };
+    let create_result = services::message::create(
+        user,
+        conversation_id,
+        send_dto,
+        state.config.message_request_limit,
+        &state.pool
+    ).await;
+    match create_result {
+        Ok(message) => {
+            publish_message(message.id, member_ids, EventPayload::MessageCreated, &state).await;
+            return (
+                StatusCode::CREATED,
+                Json(message)
+            ).into_response();
+        }
+        Err(err) => return err.into_response()
+    }
+}
+
+pub async fn update(
+    Path(id): Path<i32>,
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Json(edit_dto): Json<EditDto>
+) -> Response {
+    if let Err(err) = edit_dto.validate() {
+        return AppError::ValidationError(err.to_string()).into_response();
+    }
+    let user_id = user.id;
+    let update_result = services::message::update(
+        user,
+        id,
+        edit_dto,
+        state.config.message_edit_window,
+        &state.pool
+    ).await;
+    match update_result {
+        Ok(message) => {
+            if let Ok(member_ids) = services::conversation::get_member_ids(
+                message.conversation_id,
+                user_id,
+                &state.pool
+            ).await {
+                publish_message(message.id, member_ids, EventPayload::MessageUpdated, &state).await;
+            }
+            return (
+                StatusCode::OK,
+                Json(message)
+            ).into_response();
+        }
+        Err(err) => return err.into_response()
+    }
+}
+
+pub async fn delete(
+    Path(id): Path<i32>,
+    Query(delete_query): Query<DeleteQuery>,
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>
+) -> Response {
+    let user_id = user.id;
+    if delete_query.scope == DeleteScope::Me {
+        match services::message::hide(user, id, &state.pool).await {
+            Ok(message) => {
+                state.events.publish(
+                    vec![user_id],
+                    EventPayload::MessageHidden {
+                        id: message.id,
+                        conversation_id: message.conversation_id
+                    }
+                );
+                return (StatusCode::OK).into_response();
+            }
+            Err(err) => return err.into_response()
+        }
+    }
+    let delete_result = services::message::delete(
+        user,
+        id,
+        state.config.message_delete_window,
+        state.storage.as_ref(),
+        &state.pool
+    ).await;
+    match delete_result {
+        Ok(message) => {
+            if let Ok(member_ids) = services::conversation::get_member_ids(
+                message.conversation_id,
+                user_id,
+                &state.pool
+            ).await {
+                publish_message(message.id, member_ids, EventPayload::MessageDeleted, &state).await;
+            }
+            return (
+                StatusCode::OK,
+                Json(message)
+            ).into_response();
+        }
+        Err(err) => return err.into_response()
+    }
+}
+
+// the message is selected again for every member,
+// the `reacted_by_me` of the reactions is theirs and not the one of the user,
+// and `readed` follows their read receipts.
+async fn publish_message(
+    id: i32,
+    member_ids: Vec<i32>,
+    payload: fn(Message) -> EventPayload,
+    state: &AppState
+) {
+    for member_id in member_ids {
+        // not found when the member hid the message.
+        if let Ok(message) = services::message::find(member_id, id, &state.pool).await {
+            state.events.publish(vec![member_id], payload(message));
+        }
+    }
+}
+
+pub async fn add_reaction(
+    Path((id, emoji)): Path<(i32, String)>,
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>
+) -> Response {
+    return react(id, emoji, true, user, state).await;
+}
+
+pub async fn remove_reaction(
+    Path((id, emoji)): Path<(i32, String)>,
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>
+) -> Response {
+    return react(id, emoji, false, user, state).await;
+}
+
+async fn react(
+    id: i32,
+    emoji: String,
+    added: bool,
+    user: User,
+    state: AppState
+) -> Response {
+    if let Err(err) = emoji_validate(&emoji) {
+        return AppError::ValidationError(format!("emoji: {}", err)).into_response();
+    }
+    let emoji = normalize_emoji(&emoji);
+    let user_id = user.id;
+    let react_result = services::message::react(
+        user,
+        id,
+        emoji,
+        added,
+        &state.pool
+    ).await;
+    match react_result {
+        Ok(Some(change)) => {
+            if let Ok(member_ids) = services::conversation::get_member_ids(
+                change.conversation_id,
+                user_id,
+                &state.pool
+            ).await {
+                state.events.publish(
+                    member_ids,
+                    EventPayload::ReactionChanged(change)
+                );
+            }
+            return (StatusCode::OK).into_response();
+        }
+        Ok(None) => return (StatusCode::OK).into_response(),
+        Err(err) => return err.into_response()
+    }
+}
+
+pub async fn mark_delivered(
+    Path(conversation_id): Path<i32>,
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Json(cursor_dto): Json<CursorDto>
+) -> Response {
+    return update_cursor(conversation_id, user, state, CursorKind::Delivered, cursor_dto).await;
+}
+
+pub async fn mark_read(
+    Path(conversation_id): Path<i32>,
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Json(cursor_dto): Json<CursorDto>
+) -> Response {
+    return update_cursor(conversation_id, user, state, CursorKind::Read, cursor_dto).await;
+}
+
+async fn update_cursor(
+    conversation_id: i32,
+    user: User,
+    state: AppState,
+    kind: CursorKind,
+    cursor_dto: CursorDto
+) -> Response {
+    let member_ids = match services::conversation::get_member_ids(
+        conversation_id,
+        user.id,
+        &state.pool
+    ).await {
+        Ok(member_ids) => member_ids,
+        Err(err) => return err.into_response()
+    };
+    let user_id = user.id;
+    // the read cursor is shared only when both users share their read receipts.
+    let receipt_ids = if user.read_receipts {
+        match services::user::filter_read_receipts(member_ids.clone(), &state.pool).await {
+            Ok(receipt_ids) => receipt_ids,
+            Err(err) => return err.into_response()
+        }
+    } else {
+        Vec::new()
+    };
+    let update_result = services::message::update_cursor(
+        user,
+        conversation_id,
+        kind,
+        cursor_dto,
+        &state.pool
+    ).await;
+    match update_result {
+        Ok(cursor) => {
+            let (shared, hidden): (Vec<i32>, Vec<i32>) = member_ids
+                .into_iter()
+                .partition(|member_id| *member_id == user_id || receipt_ids.contains(member_id));
+            state.events.publish(
+                shared,
+                EventPayload::CursorUpdated(cursor.clone())
+            );
+            // nothing changed for them when only the read cursor moved.
+            if let CursorKind::Delivered = kind {
+                let mut masked = cursor.clone();
+                masked.last_read_message_id = None;
+                state.events.publish(
+                    hidden,
+                    EventPayload::CursorUpdated(masked)
+                );
+            }
+            return (
+                StatusCode::OK,
+                Json(cursor)
+            ).into_response();
+        }
+        Err(err) => return err.into_response()
+    }
+}
+
+pub async fn search(
+    Query(search_query): Query<SearchQuery>,
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>
+) -> Response {
+    if let Err(err) = search_query.validate() {
+        return AppError::ValidationError(err.to_string()).into_response();
+    }
+    let search_result = services::message::search(
+        user,
+        search_query,
+        &state.pool
+    ).await;
+    match search_result {
+        Ok(results) => return (
+                StatusCode::OK,
+                Json(results)
+            ).into_response(),
+        Err(err) => return err.into_response()
+    }
+}
@@ -1,3 +1,8 @@
 pub mod user;
 pub mod conversation;
-pub mod message;
\ No newline at end of file
+pub mod message;
+pub mod ws;
+pub mod attachment;
+pub mod tus;
+pub mod block;
+pub mod contact;
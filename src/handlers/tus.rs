@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+
+use axum::{
+    body::Body,
+    extract::{OriginalUri, Path, State},
+    http::{header, HeaderMap, HeaderName, StatusCode},
+    response::{IntoResponse, Response},
+    Json
+};
+use base64::Engine;
+use futures_util::StreamExt;
+
+use crate::{
+    error::AppError,
+    extractors::auth::AuthUser,
+    handlers::attachment::sanitize_filename,
+    middlewares::tus::TUS_VERSION,
+    modules::attachment::Upload,
+    services,
+    state::AppState,
+    utils::timestamp
+};
+
+const TUS_EXTENSIONS: &str = "creation,expiration,termination";
+const OFFSET_CONTENT_TYPE: &str = "application/offset+octet-stream";
+const UPLOAD_LENGTH: HeaderName = HeaderName::from_static("upload-length");
+const UPLOAD_OFFSET: HeaderName = HeaderName::from_static("upload-offset");
+const UPLOAD_METADATA: HeaderName = HeaderName::from_static("upload-metadata");
+const UPLOAD_EXPIRES: HeaderName = HeaderName::from_static("upload-expires");
+// the id of the pending attachment, sent once the last chunk is received.
+const ATTACHMENT_ID: HeaderName = HeaderName::from_static("attachment-id");
+
+
+// the tus resumable upload protocol, https://tus.io/protocols/resumable-upload
+pub async fn options(State(state): State<AppState>) -> Response {
+    return (
+        StatusCode::NO_CONTENT,
+        [
+            (HeaderName::from_static("tus-version"), TUS_VERSION.to_string()),
+            (HeaderName::from_static("tus-extension"), TUS_EXTENSIONS.to_string()),
+            (HeaderName::from_static("tus-max-size"), state.config.max_attachment_size.to_string()),
+        ]
+    ).into_response();
+}
+
+// the upload is found at the returned Location.
+pub async fn create(
+    Path(conversation_id): Path<i32>,
+    OriginalUri(uri): OriginalUri,
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    headers: HeaderMap
+) -> Response {
+    let Some(size) = header_number(&headers, &UPLOAD_LENGTH) else {
+        return AppError::ValidationError("Upload-Length: required".to_string()).into_response();
+    };
+    if size < 1 {
+        return AppError::ValidationError("Upload-Length: min=1".to_string()).into_response();
+    }
+    if size > state.config.max_attachment_size {
+        return AppError::PayloadTooLarge.into_response();
+    }
+    let filename = match parse_metadata(&headers) {
+        Some(metadata) => sanitize_filename(
+            metadata.get("filename").map(String::as_str).unwrap_or("file")
+        ),
+        None => return AppError::ValidationError(
+            "Upload-Metadata: invalid".to_string()
+        ).into_response()
+    };
+    if let Err(err) = services::conversation::get_member_ids(
+        conversation_id,
+        user.id,
+        &state.pool
+    ).await {
+        return err.into_response();
+    }
+    let create_result = services::attachment::create_upload(
+        &user,
+        conversation_id,
+        services::attachment::new_storage_key(),
+        filename,
+        size,
+        true,
+        state.config.resumable_upload_expires_in,
+        state.config.user_storage_quota,
+        &state.pool
+    ).await;
+    let upload = match create_result {
+        Ok(upload) => upload,
+        Err(err) => return err.into_response()
+    };
+    // created at `<uploads>/conversation/{conversation_id}`, found at `<uploads>/{upload_id}`.
+    let location = match uri.path().rsplit_once("/conversation/") {
+        Some((prefix, _)) => format!("{}/{}", prefix, upload.id),
+        None => upload.id.to_string()
+    };
+    return (
+        StatusCode::CREATED,
+        [
+            (header::LOCATION, location),
+            (UPLOAD_EXPIRES, timestamp::http_date(&upload.expires_at)),
+        ],
+        Json(upload)
+    ).into_response();
+}
+
+// how much of the upload the server has, the client resumes from there.
+pub async fn progress(
+    Path(id): Path<i32>,
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>
+) -> Response {
+    let upload = match services::attachment::find_resumable_upload(&user, id, &state.pool).await {
+        Ok(upload) => upload,
+        Err(err) => return err.into_response()
+    };
+    return (
+        StatusCode::OK,
+        upload_headers(&upload),
+        [(header::CACHE_CONTROL, "no-store")]
+    ).into_response();
+}
+
+// the chunk must start at the current offset and must not go past the length,
+// a chunk that is cut off is dropped so the client resends it.
+pub async fn append(
+    Path(id): Path<i32>,
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Body
+) -> Response {
+    if headers.get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok()) != Some(OFFSET_CONTENT_TYPE) {
+        return AppError::UnsupportedMediaType.into_response();
+    }
+    let Some(offset) = header_number(&headers, &UPLOAD_OFFSET) else {
+        return AppError::ValidationError("Upload-Offset: required".to_string()).into_response();
+    };
+    let upload = match services::attachment::find_resumable_upload(&user, id, &state.pool).await {
+        Ok(upload) => upload,
+        Err(err) => return err.into_response()
+    };
+    if offset != upload.upload_offset {
+        return AppError::Conflict.into_response();
+    }
+    let remaining = (upload.size - upload.upload_offset) as usize;
+    let mut data = Vec::new();
+    let mut stream = body.into_data_stream();
+    while let Some(chunk) = stream.next().await {
+        match chunk {
+            Ok(chunk) => {
+                if data.len() + chunk.len() > remaining {
+                    return AppError::PayloadTooLarge.into_response();
+                }
+                data.extend_from_slice(&chunk);
+            }
+            Err(err) => return AppError::ValidationError(err.to_string()).into_response()
+        }
+    }
+    let append_result = services::attachment::append_upload(
+        &user,
+        upload,
+        data,
+        state.config.resumable_upload_expires_in,
+        state.config.user_storage_quota,
+        state.storage.as_ref(),
+        &state.pool
+    ).await;
+    match append_result {
+        Ok((upload, None)) => return (
+                StatusCode::NO_CONTENT,
+                upload_headers(&upload)
+            ).into_response(),
+        Ok((upload, Some(attachment))) => return (
+                StatusCode::NO_CONTENT,
+                upload_headers(&upload),
+                [(ATTACHMENT_ID, attachment.id.to_string())]
+            ).into_response(),
+        Err(err) => return err.into_response()
+    }
+}
+
+pub async fn terminate(
+    Path(id): Path<i32>,
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>
+) -> Response {
+    match services::attachment::delete_upload(
+        &user,
+        id,
+        state.storage.as_ref(),
+        &state.pool
+    ).await {
+        Ok(_) => return StatusCode::NO_CONTENT.into_response(),
+        Err(err) => return err.into_response()
+    }
+}
+
+fn upload_headers(upload: &Upload) -> [(HeaderName, String); 3] {
+    return [
+        (UPLOAD_OFFSET, upload.upload_offset.to_string()),
+        (UPLOAD_LENGTH, upload.size.to_string()),
+        (UPLOAD_EXPIRES, timestamp::http_date(&upload.expires_at)),
+    ];
+}
+
+fn header_number(headers: &HeaderMap, name: &HeaderName) -> Option<i64> {
+    return headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .filter(|value: &i64| *value >= 0);
+}
+
+// `key base64value,key base64value,...`, a key may have no value.
+// `None` when a value is not valid base64 or utf-8.
+fn parse_metadata(headers: &HeaderMap) -> Option<HashMap<String, String>> {
+    let mut metadata = HashMap::new();
+    let Some(value) = headers.get(&UPLOAD_METADATA) else {
+        return Some(metadata);
+    };
+    for pair in value.to_str().ok()?.split(',') {
+        let mut parts = pair.trim().splitn(2, ' ');
+        let key = parts.next().unwrap_or("");
+        if key.is_empty() {
+            continue;
+        }
+        let value = match parts.next() {
+            Some(value) => String::from_utf8(
+                base64::engine::general_purpose::STANDARD.decode(value.trim()).ok()?
+            ).ok()?,
+            None => String::new()
+        };
+        metadata.insert(key.to_string(), value);
+    }
+    return Some(metadata);
+}
@@ -1,33 +1,44 @@
 use axum::{
-    extract::Path, 
-    http::StatusCode, 
+    extract::{
+        Multipart,
+        Path, 
+        Query,
+        State
+    }, 
+    http::{
+        header,
+        HeaderMap,
+        StatusCode
+    }, 
     response::{
         IntoResponse, 
         Response
     }, 
-    Extension, 
     Json
 };
 use serde_json::json;
-use sqlx::{Pool, Postgres};
 use validator::Validate;
 
 use crate::{
     error::AppError, 
+    extractors::auth::AuthUser,
     modules::user::{
         CreateDto, 
         LoginDto,
+        PrivacyDto,
+        PrivacySettings,
+        SearchQuery,
         UpdateInfoDto, 
-        UpdatePassDto, 
-        User
+        UpdatePassDto
     },
     services,
-    utils
+    state::AppState,
+    utils::{self, media}
 };
 
 
 pub async fn register(
-    Extension(pool): Extension<Pool<Postgres>>,
+    State(state): State<AppState>,
     Json(create_dto): Json<CreateDto>
 ) -> Response {
     if let Err(err) = create_dto.validate() {
@@ -35,13 +46,13 @@ pub async fn register(
     }
     let create_result = services::user::create(
         create_dto, 
-        &pool
+        &state.pool
     ).await;
     match create_result {
         Ok(user) => {
             let create_session_result = services::session::create(
                 user.id, 
-                &pool
+                &state.pool
             ).await;
             match create_session_result {
                 Ok(session) => return (
@@ -60,7 +71,7 @@ pub async fn register(
 }
 
 pub async fn login(
-    Extension(pool): Extension<Pool<Postgres>>,
+    State(state): State<AppState>,
     Json(login_dto): Json<LoginDto>
 ) -> Response {
     if let Err(e) = login_dto.validate() {
@@ -68,13 +79,13 @@ pub async fn login(
     }
     let varify_reslt = services::user::login(
         login_dto, 
-        &pool
+        &state.pool
     ).await;
     match varify_reslt {
         Ok(user) => { 
             let create_session_result = services::session::create(
                 user.id, 
-                &pool
+                &state.pool
             ).await;
             match create_session_result {
                 Ok(session) => return (
@@ -93,12 +104,12 @@ pub async fn login(
 }
 
 pub async fn logout(
-    Extension(pool): Extension<Pool<Postgres>>,
-    Extension(user): Extension<User>
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser
 ) -> Response {
     let delete_session_result = services::session::delete(
         user.id, 
-        &pool
+        &state.pool
     ).await;
     match delete_session_result {
         Ok(_) => return (
@@ -110,12 +121,12 @@ pub async fn logout(
 }
 
 pub async fn refresh(
-    Extension(pool): Extension<Pool<Postgres>>,
-    Extension(user): Extension<User>
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser
 ) -> Response {
     let create_session_result = services::session::create(
         user.id, 
-        &pool
+        &state.pool
     ).await;
     match create_session_result {
         Ok(session) => return (
@@ -128,56 +139,238 @@ pub async fn refresh(
 
 pub async fn get_information(
     Path(username): Path<String>,
-    Extension(user): Extension<User>,
-    Extension(pool): Extension<Pool<Postgres>>
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>
 ) -> Response {
     if username == user.username {
-        return (StatusCode::OK, Json(user)).into_response();
+        return (
+            StatusCode::OK,
+            [(header::ETAG, version_etag(user.version))],
+            Json(user)
+        ).into_response();
     }
-    let find_result = services::user::find(
-        username, 
-        &pool
+    let profile_result = services::user::get_profile(
+        user.id,
+        username,
+        &state.presence,
+        &state.pool
     ).await;
-    match find_result {
-        Ok(data) => return (
+    match profile_result {
+        Ok(profile) => return (
                 StatusCode::OK,
-                Json(json!({
-                    "id": data.id,
-                    "name": data.name,
-                    "username": data.username,
-                    "gender": data.gender,
-                    "email": data.email
-                }))
+                Json(profile)
+            ).into_response(),
+        Err(err) => return err.into_response()
+    }
+}
+
+pub async fn search(
+    Query(search_query): Query<SearchQuery>,
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>
+) -> Response {
+    if let Err(err) = search_query.validate() {
+        return AppError::ValidationError(err.to_string()).into_response();
+    }
+    let search_result = services::user::search(
+        &user,
+        search_query,
+        &state.pool
+    ).await;
+    match search_result {
+        Ok(results) => return (
+                StatusCode::OK,
+                Json(results)
             ).into_response(),
         Err(err) => return err.into_response()
     }
 }
 
 pub async fn update_information(
-    Extension(pool): Extension<Pool<Postgres>>,
-    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    headers: HeaderMap,
     Json(update_info_dto): Json<UpdateInfoDto>
 ) -> Response {
     if let Err(err) = update_info_dto.validate() {
         return AppError::ValidationError(err.to_string()).into_response();
     }
+    let version = match if_match_version(&headers) {
+        Ok(version) => version,
+        Err(err) => return err.into_response()
+    };
     let update_result = services::user::update_information(
         user, 
         update_info_dto, 
-        &pool
+        version,
+        &state.pool
     ).await;
     match update_result {
         Ok(data) => return (
                 StatusCode::OK,
+                [(header::ETAG, version_etag(data.version))],
                 Json(data)
             ).into_response(),
         Err(e) => return e.into_response()
     }
 }
 
+fn version_etag(version: i32) -> String {
+    return format!("\"{}\"", version);
+}
+
+// the version of the `If-Match` ETag, `None` without the header or with `*`.
+// the weak or unknown ETags never match.
+fn if_match_version(headers: &HeaderMap) -> Result<Option<i32>, AppError> {
+    let Some(if_match) = headers.get(header::IF_MATCH) else {
+        return Ok(None);
+    };
+    let if_match = if_match.to_str().map(|if_match| if_match.trim());
+    match if_match {
+        Ok("*") => return Ok(None),
+        Ok(etag) => return etag
+            .strip_prefix('"')
+            .and_then(|etag| etag.strip_suffix('"'))
+            .and_then(|version| version.parse().ok())
+            .map(Some)
+            .ok_or(AppError::PreconditionFailed),
+        Err(_) => return Err(AppError::PreconditionFailed)
+    }
+}
+
+pub async fn get_privacy(
+    AuthUser(user): AuthUser
+) -> Response {
+    return (
+        StatusCode::OK,
+        Json(PrivacySettings::from(&user))
+    ).into_response();
+}
+
+pub async fn update_privacy(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Json(privacy_dto): Json<PrivacyDto>
+) -> Response {
+    let update_result = services::user::update_privacy(
+        user,
+        privacy_dto,
+        &state.pool
+    ).await;
+    match update_result {
+        Ok(data) => return (
+                StatusCode::OK,
+                Json(PrivacySettings::from(&data))
+            ).into_response(),
+        Err(err) => return err.into_response()
+    }
+}
+
+pub async fn update_avatar(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    mut multipart: Multipart
+) -> Response {
+    let mut field = loop {
+        match multipart.next_field().await {
+            Ok(Some(field)) => {
+                if field.name() == Some("file") {
+                    break field;
+                }
+            }
+            Ok(None) => return AppError::ValidationError("file: required".to_string()).into_response(),
+            Err(err) => return AppError::ValidationError(err.body_text()).into_response()
+        }
+    };
+    let mut data = Vec::new();
+    loop {
+        match field.chunk().await {
+            Ok(Some(chunk)) => {
+                if (data.len() + chunk.len()) as i64 > state.config.max_avatar_size {
+                    return AppError::PayloadTooLarge.into_response();
+                }
+                data.extend_from_slice(&chunk);
+            }
+            Ok(None) => break,
+            Err(err) => return AppError::ValidationError(err.body_text()).into_response()
+        }
+    }
+    let update_result = services::user::update_avatar(
+        user,
+        data,
+        state.storage.as_ref(),
+        &state.pool
+    ).await;
+    match update_result {
+        Ok(data) => return (
+                StatusCode::OK,
+                Json(data)
+            ).into_response(),
+        Err(err) => return err.into_response()
+    }
+}
+
+pub async fn delete_avatar(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>
+) -> Response {
+    let delete_result = services::user::delete_avatar(
+        user,
+        state.storage.as_ref(),
+        &state.pool
+    ).await;
+    match delete_result {
+        Ok(data) => return (
+                StatusCode::OK,
+                Json(data)
+            ).into_response(),
+        Err(err) => return err.into_response()
+    }
+}
+
+// the url stays the same when the avatar changes, so the clients revalidate it.
+pub async fn get_avatar(
+    Path((username, size)): Path<(String, u32)>,
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    headers: HeaderMap
+) -> Response {
+    let get_result = services::user::get_avatar(
+        user.id,
+        username,
+        size,
+        state.storage.as_ref(),
+        &state.pool
+    ).await;
+    let (avatar_id, data) = match get_result {
+        Ok(avatar) => avatar,
+        Err(err) => return err.into_response()
+    };
+    let etag = format!("\"{}-{}\"", avatar_id, size);
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|if_none_match| if_none_match.to_str().ok())
+        .is_some_and(|if_none_match| utils::etag_matches(if_none_match, &etag));
+    if not_modified {
+        return (
+            StatusCode::NOT_MODIFIED,
+            [(header::ETAG, etag)]
+        ).into_response();
+    }
+    return (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, media::AVATAR_MIME_TYPE.to_string()),
+            (header::CACHE_CONTROL, "private, no-cache".to_string()),
+            (header::ETAG, etag),
+        ],
+        data
+    ).into_response();
+}
+
 pub async fn update_password(
-    Extension(user): Extension<User>,
-    Extension(pool): Extension<Pool<Postgres>>,
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
     Json(update_pass_dto): Json<UpdatePassDto>
 ) -> Response {
     if let Err(err) = update_pass_dto.validate() {
@@ -186,7 +379,7 @@ pub async fn update_password(
     let update_result = services::user::update_password(
         user, 
         update_pass_dto, 
-        &pool
+        &state.pool
     ).await;
     match update_result {
         Ok(_) => return (StatusCode::OK).into_response(),
@@ -195,12 +388,13 @@ pub async fn update_password(
 }
 
 pub async fn delete(
-    Extension(user): Extension<User>,
-    Extension(pool): Extension<Pool<Postgres>>
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>
 ) -> Response {
     let delete_result = services::user::delete(
         user, 
-        &pool
+        state.storage.as_ref(),
+        &state.pool
     ).await;
     match delete_result {
         Ok(_) => return (
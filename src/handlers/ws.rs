@@ -0,0 +1,206 @@
+use std::{collections::HashMap, time::Duration};
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        State
+    },
+    response::Response
+};
+use chrono::Utc;
+use tokio::{sync::broadcast::error::RecvError, time::Instant};
+use tracing::error;
+
+use crate::{
+    events::{ClientEvent, EventPayload},
+    extractors::auth::AuthUser,
+    modules::user::{PresenceView, User, Visibility},
+    services,
+    state::AppState
+};
+
+// the client repeats `typing_started` while the user types,
+// the repeats within TYPING_THROTTLE only keep the typing alive.
+const TYPING_THROTTLE: Duration = Duration::from_secs(3);
+const TYPING_TIMEOUT: Duration = Duration::from_secs(6);
+
+
+// a conversation the user of this socket is typing in.
+struct Typing {
+    // the other members of the conversation.
+    recipients: Vec<i32>,
+    last_sent_at: Instant,
+    expires_at: Instant,
+}
+
+pub async fn connect(
+    ws: WebSocketUpgrade,
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>
+) -> Response {
+    return ws.on_upgrade(move |socket| handle_socket(socket, user, state));
+}
+
+async fn handle_socket(mut socket: WebSocket, user: User, state: AppState) {
+    let mut events = state.events.subscribe();
+    if state.presence.connect(user.id) {
+        publish_presence(&user, true, &state).await;
+    }
+    let mut typing: HashMap<i32, Typing> = HashMap::new();
+    loop {
+        let next_expiry = typing.values().map(|typing| typing.expires_at).min();
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => {
+                    if !event.recipients.contains(&user.id) {
+                        continue;
+                    }
+                    // the message ends the typing, the recipients clear it when it arrives.
+                    if let EventPayload::MessageCreated(message) = &event.payload &&
+                        message.sender_id == Some(user.id) {
+                        typing.remove(&message.conversation_id);
+                    }
+                    let text = match serde_json::to_string(&event.payload) {
+                        Ok(text) => text,
+                        Err(err) => {
+                            error!("{:#?}", err);
+                            continue;
+                        }
+                    };
+                    if socket.send(Message::Text(text.into())).await.is_err() {
+                        break;
+                    }
+                }
+                // the client is too slow, skip the missed events.
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            },
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Text(text))) => {
+                    match serde_json::from_str::<ClientEvent>(&text) {
+                        Ok(ClientEvent::TypingStarted { conversation_id }) => {
+                            start_typing(&mut typing, conversation_id, &user, &state).await;
+                        }
+                        Ok(ClientEvent::TypingStopped { conversation_id }) => {
+                            stop_typing(&mut typing, conversation_id, &user, &state);
+                        }
+                        // unknown events are ignored.
+                        Err(_) => continue
+                    }
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+            _ = tokio::time::sleep_until(next_expiry.unwrap_or_else(Instant::now)), if next_expiry.is_some() => {
+                let now = Instant::now();
+                let expired = typing
+                    .iter()
+                    .filter(|(_, typing)| typing.expires_at <= now)
+                    .map(|(conversation_id, _)| *conversation_id)
+                    .collect::<Vec<_>>();
+                for conversation_id in expired {
+                    stop_typing(&mut typing, conversation_id, &user, &state);
+                }
+            }
+        }
+    }
+    // the socket is gone, nobody will stop the typing.
+    let conversation_ids = typing.keys().copied().collect::<Vec<_>>();
+    for conversation_id in conversation_ids {
+        stop_typing(&mut typing, conversation_id, &user, &state);
+    }
+    if state.presence.disconnect(user.id) {
+        publish_presence(&user, false, &state).await;
+    }
+}
+
+// sent to the contacts, and to the conversation peers when everyone can see the presence.
+// the visibility is read again, the user may have changed it while connected.
+async fn publish_presence(user: &User, online: bool, state: &AppState) {
+    let Some(visibility) = services::user::touch(user.id, &state.pool).await else {
+        return;
+    };
+    let mut recipients = match visibility {
+        Visibility::Nobody => return,
+        Visibility::Contacts => Vec::new(),
+        Visibility::Everyone => match services::conversation::get_peer_ids(user.id, &state.pool).await {
+            Ok(peer_ids) => peer_ids,
+            Err(_) => return
+        }
+    };
+    match services::contact::get_contact_ids(user.id, &state.pool).await {
+        Ok(contact_ids) => recipients.extend(contact_ids),
+        Err(_) => return
+    }
+    recipients.sort();
+    recipients.dedup();
+    state.events.publish(
+        recipients,
+        EventPayload::PresenceChanged {
+            user_id: user.id,
+            presence: PresenceView {
+                online,
+                last_seen_at: Some(Utc::now())
+            }
+        }
+    );
+}
+
+async fn start_typing(
+    typing: &mut HashMap<i32, Typing>,
+    conversation_id: i32,
+    user: &User,
+    state: &AppState
+) {
+    let now = Instant::now();
+    if let Some(current) = typing.get_mut(&conversation_id) {
+        current.expires_at = now + TYPING_TIMEOUT;
+        if now - current.last_sent_at < TYPING_THROTTLE {
+            return;
+        }
+    }
+    // checked again on every sent event, the user may have left the conversation.
+    let recipients = match services::conversation::get_member_ids(
+        conversation_id,
+        user.id,
+        &state.pool
+    ).await {
+        Ok(member_ids) => member_ids
+            .into_iter()
+            .filter(|member_id| *member_id != user.id)
+            .collect::<Vec<_>>(),
+        Err(_) => {
+            stop_typing(typing, conversation_id, user, state);
+            return;
+        }
+    };
+    // the blocked users can not message each other, so they do not see the typing either.
+    let blocked = services::block::exists_in_conversation(conversation_id, user.id, &state.pool).await;
+    if !matches!(blocked, Ok(false)) {
+        stop_typing(typing, conversation_id, user, state);
+        return;
+    }
+    state.events.publish(
+        recipients.clone(),
+        EventPayload::TypingStarted { conversation_id, user_id: user.id }
+    );
+    typing.insert(conversation_id, Typing {
+        recipients,
+        last_sent_at: now,
+        expires_at: now + TYPING_TIMEOUT
+    });
+}
+
+fn stop_typing(
+    typing: &mut HashMap<i32, Typing>,
+    conversation_id: i32,
+    user: &User,
+    state: &AppState
+) {
+    if let Some(stopped) = typing.remove(&conversation_id) {
+        state.events.publish(
+            stopped.recipients,
+            EventPayload::TypingStopped { conversation_id, user_id: user.id }
+        );
+    }
+}
@@ -0,0 +1,19 @@
+use tracing::info;
+
+
+// the mailer only logs the outgoing mails for now,
+// plug a real transport here when we have one.
+pub struct Mailer {
+    from: String,
+}
+
+impl Mailer {
+    pub fn new(from: String) -> Self {
+        return Self { from };
+    }
+
+    #[allow(dead_code)]
+    pub fn send(&self, to: &str, subject: &str, body: &str) {
+        info!("mail from '{}' to '{}': {} - {}", self.from, to, subject, body);
+    }
+}
@@ -1,4 +1,8 @@
-use axum::{middleware, Extension, Router};
+#![allow(clippy::needless_return)]
+
+use std::sync::Arc;
+
+use axum::{middleware, Router};
 use tracing::info;
 use dotenvy::dotenv;
 
@@ -10,26 +14,50 @@ mod modules;
 mod error;
 mod services;
 mod utils;
+mod config;
+mod events;
+mod presence;
+mod mailer;
+mod state;
+mod extractors;
+mod storage;
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
     tracing_subscriber::fmt::init();
-    let db_conn = db::create_db_connection().await;
+    let config = config::Config::from_env();
+    let db_conn = db::create_db_connection(&config).await;
     info!("Database connection created.");
-    let app = Router::new()
-        .nest("/api/v1", routes::main())
-        .layer(middleware::from_fn(middlewares::logger::log_request))
-        .layer(Extension(db_conn));
-    let port = std::env::var("CHAT_APP_PORT")
-        .expect(">>> CHAT_APP_PORT NOT found!");
+    let state = state::AppState {
+        pool: db_conn,
+        mailer: Arc::new(mailer::Mailer::new(config.mail_from.clone())),
+        events: events::EventBus::new(1024),
+        presence: presence::Presence::new(),
+        storage: storage::from_config(&config),
+        config: Arc::new(config),
+    };
+    tokio::spawn(delete_expired_uploads(state.clone()));
     let listener = tokio::net::TcpListener::bind(
-        format!("127.0.0.1:{}", port)
+        format!("127.0.0.1:{}", state.config.port)
     )
         .await
         .expect(">>> Can NOT create the listener!");
+    let app = Router::new()
+        .nest("/api/v1", routes::main())
+        .layer(middleware::from_fn(middlewares::logger::log_request))
+        .with_state(state);
     info!("server running on: {}", listener.local_addr().unwrap());
     axum::serve(listener, app)
         .await
         .expect(">>> Axum can NOT serve us!");
 }
+
+// the abandoned uploads are swept every few minutes.
+async fn delete_expired_uploads(state: state::AppState) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(5 * 60));
+    loop {
+        interval.tick().await;
+        services::attachment::delete_expired_uploads(state.storage.as_ref(), &state.pool).await;
+    }
+}
@@ -1,2 +1,2 @@
 pub mod logger;
-pub mod auth;
\ No newline at end of file
+pub mod tus;
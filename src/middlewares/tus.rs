@@ -0,0 +1,30 @@
+use axum::{
+    extract::Request,
+    http::{header::HeaderName, HeaderValue, Method},
+    middleware::Next,
+    response::{IntoResponse, Response}
+};
+
+use crate::error::AppError;
+
+pub const TUS_VERSION: &str = "1.0.0";
+pub const TUS_RESUMABLE: HeaderName = HeaderName::from_static("tus-resumable");
+
+
+// every tus request but OPTIONS names the protocol version,
+// and every response names the version of the server.
+pub async fn check_version(req: Request, next: Next) -> Response {
+    let version = req.headers().get(&TUS_RESUMABLE).and_then(|value| value.to_str().ok());
+    let mut res = if req.method() != Method::OPTIONS && version != Some(TUS_VERSION) {
+        let mut res = AppError::PreconditionFailed.into_response();
+        res.headers_mut().insert(
+            HeaderName::from_static("tus-version"),
+            HeaderValue::from_static(TUS_VERSION)
+        );
+        res
+    } else {
+        next.run(req).await
+    };
+    res.headers_mut().insert(TUS_RESUMABLE, HeaderValue::from_static(TUS_VERSION));
+    return res;
+}
@@ -0,0 +1,80 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::utils::timestamp;
+
+
+#[derive(Serialize, sqlx::FromRow, Clone)]
+pub struct Attachment {
+    pub id: i32,
+    pub conversation_id: i32,
+    // `None` until the attachment is sent with a message.
+    pub message_id: Option<i32>,
+    pub uploader_id: Option<i32>,
+    #[serde(skip)]
+    pub storage_key: String,
+    pub filename: String,
+    // sniffed from the content, not taken from the client.
+    pub mime_type: String,
+    pub size: i64,
+    // sha256 of the content in hex.
+    pub checksum: String,
+    // set only for the images, which also have a thumbnail.
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub blurhash: Option<String>,
+    #[serde(skip)]
+    pub thumbnail_key: Option<String>,
+    #[serde(serialize_with = "timestamp::serialize")]
+    pub created_at: DateTime<Utc>,
+}
+
+// the attachment as embedded in the `Message` payload.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AttachmentSummary {
+    pub id: i32,
+    pub filename: String,
+    pub mime_type: String,
+    pub size: i64,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub blurhash: Option<String>,
+}
+
+#[derive(Serialize, sqlx::FromRow, Clone)]
+pub struct Upload {
+    pub id: i32,
+    pub conversation_id: i32,
+    pub uploader_id: i32,
+    #[serde(skip)]
+    pub storage_key: String,
+    pub filename: String,
+    // the size the client declared, the uploaded file must match it.
+    pub size: i64,
+    // sent in chunks through the server instead of straight to the storage.
+    pub resumable: bool,
+    // how many bytes of a resumable upload the server has received.
+    pub upload_offset: i64,
+    // the stored chunks of a resumable upload, in order.
+    #[serde(skip)]
+    pub part_keys: Vec<String>,
+    #[serde(serialize_with = "timestamp::serialize")]
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Validate, Deserialize)]
+pub struct PresignDto {
+    #[validate(length(min=1, max=255, message="min=1, max=255"))]
+    pub filename: String,
+    #[validate(range(min=1, message="min=1"))]
+    pub size: i64,
+}
+
+// where and how the client sends the file.
+#[derive(Serialize)]
+pub struct PresignedUpload {
+    pub upload: Upload,
+    pub method: String,
+    pub url: String,
+}
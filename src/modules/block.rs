@@ -0,0 +1,14 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::utils::timestamp;
+
+
+#[derive(Serialize, sqlx::FromRow, Clone)]
+pub struct BlockedUser {
+    pub id: i32,
+    pub username: String,
+    pub name: String,
+    #[serde(serialize_with = "timestamp::serialize")]
+    pub blocked_at: DateTime<Utc>,
+}
@@ -0,0 +1,46 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::utils::timestamp;
+
+
+#[derive(Serialize, sqlx::FromRow, Clone)]
+pub struct Contact {
+    pub id: i32,
+    pub username: String,
+    pub name: String,
+    // hidden when the contact shows the gender to nobody.
+    pub gender: Option<bool>,
+    // hidden when the contact shows the last seen to nobody.
+    #[sqlx(skip)]
+    pub online: bool,
+    #[serde(serialize_with = "timestamp::serialize_option")]
+    pub last_seen_at: Option<DateTime<Utc>>,
+    #[serde(skip)]
+    pub presence_visible: bool,
+    // when the request was accepted.
+    #[serde(serialize_with = "timestamp::serialize")]
+    pub since: DateTime<Utc>,
+}
+
+// a pending request, sent to or by the user.
+#[derive(Serialize, sqlx::FromRow, Clone)]
+pub struct ContactRequest {
+    pub id: i32,
+    pub username: String,
+    pub name: String,
+    // `true` when the other user sent it.
+    pub incoming: bool,
+    #[serde(serialize_with = "timestamp::serialize")]
+    pub created_at: DateTime<Utc>,
+}
+
+// the relation between the user and another one.
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ContactStatus {
+    None,
+    Contact,
+    RequestSent,
+    RequestReceived,
+}
@@ -1,12 +1,65 @@
-use serde::Serialize;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::timestamp;
 
 
 #[derive(Serialize, sqlx::FromRow, Clone)]
 pub struct Conversation {
     pub id: i32,
-    pub user1_id: i32,
-    pub user2_id: i32,
+    // the participant ids are `None` after the account is deleted.
+    pub user1_id: Option<i32>,
+    pub user2_id: Option<i32>,
+    // a message request from `user1_id` that `user2_id` has not accepted yet.
+    pub pending: bool,
+    pub last_message: Option<String>,
+    #[serde(serialize_with = "timestamp::serialize")]
+    pub created_at: DateTime<Utc>,
+    #[serde(serialize_with = "timestamp::serialize")]
+    pub updated_at: DateTime<Utc>,
+}
+
+// a row of the conversation list, seen from one of the participants.
+#[derive(Serialize, sqlx::FromRow, Clone)]
+pub struct ConversationView {
+    pub id: i32,
+    // the other participant, `peer_id` is `None` after the account is deleted
+    // and the `peer_name` is then `DELETED_USER_NAME`.
+    pub peer_id: Option<i32>,
+    pub peer_username: Option<String>,
+    pub peer_name: String,
+    // hidden unless the `gender_visibility` of the peer allows the user.
+    pub peer_gender: Option<bool>,
+    // only listed for the initiator, the recipient finds it in the requests.
+    pub pending: bool,
+    // hidden unless the peer shows the last seen to the contacts.
+    #[sqlx(skip)]
+    pub peer_online: bool,
+    #[serde(serialize_with = "timestamp::serialize_option")]
+    pub peer_last_seen_at: Option<DateTime<Utc>>,
+    #[serde(skip)]
+    pub peer_presence_visible: bool,
+    pub last_message_id: Option<i32>,
+    // `None` when the last message is deleted for everyone.
     pub last_message: Option<String>,
-    pub created_at: String,
-    pub updated_at: String,
+    pub last_message_deleted: bool,
+    pub last_message_sender_id: Option<i32>,
+    #[serde(serialize_with = "timestamp::serialize_option")]
+    pub last_message_at: Option<DateTime<Utc>>,
+    pub unread_count: i64,
+    // the last message time or the creation time for empty conversations,
+    // the list is sorted by it and it is used as the pagination cursor.
+    #[serde(serialize_with = "timestamp::serialize")]
+    pub last_activity_at: DateTime<Utc>,
+    #[serde(serialize_with = "timestamp::serialize")]
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+pub struct ListQuery {
+    pub limit: Option<i64>,
+    // return the conversations older than the (`before`, `before_id`) cursor,
+    // both are taken from the last item of the previous page.
+    pub before: Option<DateTime<Utc>>,
+    pub before_id: Option<i32>,
 }
@@ -1,14 +1,163 @@
-use serde::Serialize;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use validator::{Validate, ValidationError};
+
+use crate::{modules::attachment::AttachmentSummary, utils::timestamp};
 
 
 #[derive(Serialize, sqlx::FromRow, Clone)]
 pub struct Message {
     pub id: i32,
-    pub sender_username: String,
-    pub receiver_username: String,
     pub conversation_id: i32,
+    // `None` when the sender deleted the account,
+    // the `sender_name` is then `DELETED_USER_NAME`.
+    pub sender_id: Option<i32>,
+    pub sender_username: Option<String>,
+    pub sender_name: String,
+    // empty when the message is deleted for everyone.
     pub body: String,
+    pub deleted: bool,
+    pub reply_to: Option<Json<ReplyPreview>>,
+    pub reactions: Json<Vec<ReactionSummary>>,
+    pub attachments: Json<Vec<AttachmentSummary>>,
+    // derived from the members cursors,
+    // true once every other member has reached this message.
+    // `readed` stays false when the viewer or the member does not share the read receipts.
     pub delivered: bool,
     pub readed: bool,
-    pub created_at: String,
-}
\ No newline at end of file
+    #[serde(serialize_with = "timestamp::serialize")]
+    pub created_at: DateTime<Utc>,
+    #[serde(serialize_with = "timestamp::serialize_option")]
+    pub edited_at: Option<DateTime<Utc>>,
+}
+
+// the quoted message embedded in a reply, the body is cut to 100 characters.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ReplyPreview {
+    pub id: i32,
+    pub sender_id: Option<i32>,
+    pub sender_name: String,
+    pub body: String,
+    pub deleted: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ReactionSummary {
+    pub emoji: String,
+    pub count: i64,
+    pub reacted_by_me: bool,
+}
+
+// sent to the members when someone adds or removes a reaction,
+// the clients update their `ReactionSummary` from it.
+#[derive(Serialize, Clone)]
+pub struct ReactionChange {
+    pub message_id: i32,
+    pub conversation_id: i32,
+    pub user_id: i32,
+    pub emoji: String,
+    pub added: bool,
+}
+
+// how far a member of the conversation has received and read.
+#[derive(Serialize, sqlx::FromRow, Clone)]
+pub struct ReadCursor {
+    pub conversation_id: i32,
+    pub user_id: i32,
+    pub last_delivered_message_id: Option<i32>,
+    pub last_read_message_id: Option<i32>,
+}
+
+#[derive(Serialize)]
+pub struct MessageList {
+    pub messages: Vec<Message>,
+    pub cursors: Vec<ReadCursor>,
+}
+
+#[derive(Validate, Deserialize)]
+#[validate(schema(function="send_validate"))]
+pub struct SendDto {
+    // can be empty only when the message has attachments.
+    #[validate(length(max=4000, message="max=4000"))]
+    pub body: String,
+    // must be a message of the same conversation.
+    pub reply_to_message_id: Option<i32>,
+    // pending attachments uploaded by the sender to this conversation.
+    #[serde(default)]
+    #[validate(length(max=10, message="max=10"))]
+    pub attachment_ids: Vec<i32>,
+}
+
+#[derive(Validate, Deserialize)]
+pub struct EditDto {
+    #[validate(length(min=1, max=4000, message="min=1, max=4000"))]
+    pub body: String,
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeleteScope {
+    #[default]
+    Me,
+    Everyone,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteQuery {
+    #[serde(default)]
+    pub scope: DeleteScope,
+}
+
+#[derive(Validate, Deserialize)]
+pub struct SearchQuery {
+    #[validate(length(min=1, max=200, message="min=1, max=200"))]
+    pub q: String,
+    pub conversation_id: Option<i32>,
+    // only the messages sent in [from, before).
+    pub from: Option<DateTime<Utc>>,
+    pub before: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct SearchResult {
+    pub message_id: i32,
+    pub conversation_id: i32,
+    pub sender_id: Option<i32>,
+    pub sender_name: String,
+    // html escaped, the matched words are wrapped in <mark></mark>.
+    pub snippet: String,
+    pub rank: f32,
+    #[serde(serialize_with = "timestamp::serialize")]
+    pub created_at: DateTime<Utc>,
+}
+
+fn send_validate(send_dto: &SendDto) -> Result<(), ValidationError> {
+    if send_dto.body.trim().is_empty() && send_dto.attachment_ids.is_empty() {
+        return Err(ValidationError::new("body or attachment_ids is required"));
+    }
+    return Ok(());
+}
+
+// one emoji of the unicode emoji list, with its skin tone if any.
+pub fn emoji_validate(emoji: &str) -> Result<(), ValidationError> {
+    if emojis::get(emoji).is_none() {
+        return Err(ValidationError::new("must be an emoji"));
+    }
+    return Ok(());
+}
+
+// the fully qualified form, "❤" and "❤️" are the same reaction.
+pub fn normalize_emoji(emoji: &str) -> String {
+    return match emojis::get(emoji) {
+        Some(found) => found.as_str().to_string(),
+        None => emoji.to_string()
+    };
+}
+
+#[derive(Deserialize)]
+pub struct CursorDto {
+    pub message_id: i32,
+}
@@ -1,3 +1,6 @@
 pub mod user;
 pub mod conversation;
-pub mod message;
\ No newline at end of file
+pub mod message;
+pub mod attachment;
+pub mod block;
+pub mod contact;
@@ -1,7 +1,16 @@
 use regex::Regex;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use validator::{Validate, ValidationError};
 
+use crate::{
+    modules::contact::ContactStatus,
+    utils::{patch::Patch, timestamp}
+};
+
+// shown in place of the name of a user that deleted the account.
+pub const DELETED_USER_NAME: &str = "Deleted user";
+
 #[derive(Serialize, sqlx::FromRow, Clone)]
 pub struct User {
     pub id: i32,
@@ -10,9 +19,46 @@ pub struct User {
     #[serde(skip)]
     pub password: String,
     pub email: String,
-    pub gender: bool,
-    pub create_at: String,
-    pub update_at: String,
+    pub gender: Option<bool>,
+    #[serde(serialize_with = "timestamp::serialize")]
+    pub create_at: DateTime<Utc>,
+    #[serde(serialize_with = "timestamp::serialize")]
+    pub update_at: DateTime<Utc>,
+    // the last request or WebSocket activity of the user.
+    #[serde(serialize_with = "timestamp::serialize_option")]
+    pub last_seen_at: Option<DateTime<Utc>>,
+    // who can see `last_seen_at` and whether the user is online.
+    pub last_seen_visibility: Visibility,
+    pub email_visibility: Visibility,
+    pub gender_visibility: Visibility,
+    // who can start a conversation with the user.
+    pub conversation_visibility: Visibility,
+    // when `false` only the contacts and the conversation peers can look the user up.
+    pub discoverable: bool,
+    // when `false` the read cursor of the user is not shared,
+    // and the user does not see the read cursors of the others either.
+    pub read_receipts: bool,
+    // changes with every upload, the avatars are served at `/user/avatar/{username}/{size}`.
+    pub avatar_id: Option<String>,
+    pub bio: Option<String>,
+    pub status_text: Option<String>,
+    // the status is hidden from the others after it, `None` keeps it until it is changed.
+    #[serde(serialize_with = "timestamp::serialize_option")]
+    pub status_expires_at: Option<DateTime<Utc>>,
+    // an IANA time zone name, e.g. `Europe/Paris`.
+    pub time_zone: Option<String>,
+    // bumped by every edit of the profile, it is the ETag of the user.
+    pub version: i32,
+}
+
+// `contacts` are the users that accepted a contact request from or to the user.
+#[derive(Serialize, Deserialize, sqlx::Type, Clone, Copy, PartialEq)]
+#[sqlx(type_name = "visibility", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum Visibility {
+    Everyone,
+    Contacts,
+    Nobody,
 }
 
 #[derive(Validate, Deserialize)]
@@ -39,6 +85,7 @@ pub struct LoginDto {
     pub password: String,
 }
 
+// the missing fields are not changed, null clears the `Patch` fields.
 #[derive(Validate, Deserialize)]
 pub struct UpdateInfoDto {
     #[validate(length(min=2, max=100, message="min=2, max=100"))]
@@ -50,7 +97,104 @@ pub struct UpdateInfoDto {
         email
     )]
     pub email: Option<String>,
+    #[serde(default)]
+    pub gender: Patch<bool>,
+    #[serde(default)]
+    #[validate(length(max=500, message="max=500"))]
+    pub bio: Patch<String>,
+    // clearing the status clears its expiry too.
+    #[serde(default)]
+    #[validate(length(max=100, message="max=100"))]
+    pub status_text: Patch<String>,
+    // applies to the `status_text` sent with it, or to the current one when it is not sent.
+    #[serde(default)]
+    pub status_expires_at: Patch<DateTime<Utc>>,
+    #[serde(default)]
+    #[validate(custom(function="time_zone_validate"))]
+    pub time_zone: Patch<String>,
+}
+
+#[derive(Serialize)]
+pub struct PrivacySettings {
+    pub last_seen_visibility: Visibility,
+    pub email_visibility: Visibility,
+    pub gender_visibility: Visibility,
+    pub conversation_visibility: Visibility,
+    pub discoverable: bool,
+    pub read_receipts: bool,
+}
+
+impl From<&User> for PrivacySettings {
+    fn from(user: &User) -> Self {
+        return Self {
+            last_seen_visibility: user.last_seen_visibility,
+            email_visibility: user.email_visibility,
+            gender_visibility: user.gender_visibility,
+            conversation_visibility: user.conversation_visibility,
+            discoverable: user.discoverable,
+            read_receipts: user.read_receipts,
+        };
+    }
+}
+
+// the missing fields are not changed.
+#[derive(Deserialize)]
+pub struct PrivacyDto {
+    pub last_seen_visibility: Option<Visibility>,
+    pub email_visibility: Option<Visibility>,
+    pub gender_visibility: Option<Visibility>,
+    pub conversation_visibility: Option<Visibility>,
+    pub discoverable: Option<bool>,
+    pub read_receipts: Option<bool>,
+}
+
+// another user as seen by the viewer, filtered by the privacy settings of that user.
+#[derive(Serialize)]
+pub struct Profile {
+    pub id: i32,
+    pub name: String,
+    pub username: String,
+    // `None` unless the `gender_visibility` of the user allows the viewer.
     pub gender: Option<bool>,
+    // `None` unless the `email_visibility` of the user allows the viewer.
+    pub email: Option<String>,
+    pub avatar_id: Option<String>,
+    pub bio: Option<String>,
+    // `None` once the status expired.
+    pub status_text: Option<String>,
+    #[serde(serialize_with = "timestamp::serialize_option")]
+    pub status_expires_at: Option<DateTime<Utc>>,
+    pub time_zone: Option<String>,
+    pub presence: PresenceView,
+    pub contact: ContactStatus,
+}
+
+// the presence of another user, both are hidden by the `last_seen_visibility` of that user.
+#[derive(Serialize, Clone)]
+pub struct PresenceView {
+    pub online: bool,
+    #[serde(serialize_with = "timestamp::serialize_option")]
+    pub last_seen_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Validate, Deserialize)]
+pub struct SearchQuery {
+    #[validate(length(min=1, max=100, message="min=1, max=100"))]
+    pub q: String,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+// the contacts come first, then the prefix matches, then the closest fuzzy matches.
+#[derive(Serialize, sqlx::FromRow)]
+pub struct SearchResult {
+    pub id: i32,
+    pub username: String,
+    pub name: String,
+    // `None` unless the `gender_visibility` of the user allows the viewer.
+    pub gender: Option<bool>,
+    pub is_contact: bool,
+    pub rank: f32,
 }
 
 #[derive(Validate, Deserialize)]
@@ -90,3 +234,11 @@ fn password_validate(password: &str) -> Result<(), ValidationError> {
     }
     Ok(())
 }
+
+fn time_zone_validate(time_zone: &Patch<String>) -> Result<(), ValidationError> {
+    if let Patch::Value(time_zone) = time_zone &&
+        time_zone.parse::<chrono_tz::Tz>().is_err() {
+        return Err(ValidationError::new("must be an IANA time zone, e.g. Europe/Paris"));
+    }
+    return Ok(());
+}
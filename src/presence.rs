@@ -0,0 +1,44 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex}
+};
+
+
+// the users with a live WebSocket, a user is online while any of
+// their sessions is connected. kept in memory, like the `EventBus`.
+#[derive(Clone)]
+pub struct Presence {
+    connections: Arc<Mutex<HashMap<i32, usize>>>,
+}
+
+impl Presence {
+    pub fn new() -> Self {
+        return Self { connections: Arc::new(Mutex::new(HashMap::new())) };
+    }
+
+    // true for the first connection of the user.
+    pub fn connect(&self, user_id: i32) -> bool {
+        let mut connections = self.connections.lock().unwrap();
+        let count = connections.entry(user_id).or_insert(0);
+        *count += 1;
+        return *count == 1;
+    }
+
+    // true for the last connection of the user.
+    pub fn disconnect(&self, user_id: i32) -> bool {
+        let mut connections = self.connections.lock().unwrap();
+        let Some(count) = connections.get_mut(&user_id) else {
+            return false;
+        };
+        *count -= 1;
+        if *count == 0 {
+            connections.remove(&user_id);
+            return true;
+        }
+        return false;
+    }
+
+    pub fn is_online(&self, user_id: i32) -> bool {
+        return self.connections.lock().unwrap().contains_key(&user_id);
+    }
+}
@@ -0,0 +1,17 @@
+use axum::{extract::DefaultBodyLimit, routing::{get, post}, Router};
+
+use crate::{handlers::attachment, state::AppState};
+
+
+pub fn main() -> Router<AppState> {
+    Router::new()
+        // the size of every file is checked against the config while reading it.
+        .route(
+            "/upload/{conversation_id}",
+            post(attachment::upload).layer(DefaultBodyLimit::disable())
+        )
+        .route("/presign/{conversation_id}", post(attachment::presign))
+        .route("/complete/{id}", post(attachment::complete))
+        .route("/{id}", get(attachment::download))
+        .route("/{id}/thumbnail", get(attachment::thumbnail))
+}
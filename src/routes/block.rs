@@ -0,0 +1,11 @@
+use axum::{routing::{get, put}, Router};
+
+use crate::{handlers::block, state::AppState};
+
+
+pub fn main() -> Router<AppState> {
+    Router::new()
+        .route("/", get(block::get_all))
+        // blocking is idempotent, blocking a user twice keeps the first block.
+        .route("/{username}", put(block::create).delete(block::delete))
+}
@@ -0,0 +1,27 @@
+use axum::{
+    routing::{
+        delete,
+        get,
+        post
+    },
+    Router
+};
+
+use crate::{
+    handlers::contact,
+    state::AppState
+};
+
+pub fn main() -> Router<AppState> {
+    return Router::new()
+        .route("/", get(contact::get_all))
+        .route("/delete/{username}", delete(contact::delete))
+        .route("/requests", get(contact::get_requests))
+        // sending a request to a user that already sent one accepts it.
+        .route(
+            "/requests/{username}",
+            post(contact::send_request).delete(contact::cancel)
+        )
+        .route("/requests/{username}/accept", post(contact::accept))
+        .route("/requests/{username}/decline", post(contact::decline));
+}
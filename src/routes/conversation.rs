@@ -1,5 +1,4 @@
 use axum::{
-    middleware, 
     routing::{
         delete, 
         get, 
@@ -10,13 +9,16 @@ use axum::{
 
 use crate::{
     handlers::conversation, 
-    middlewares
+    state::AppState
 };
 
-pub fn main() -> Router {
+pub fn main() -> Router<AppState> {
     return Router::new()
         .route("/create/{username}", post(conversation::create))
         .route("/", get(conversation::get_all))
         .route("/delete/{id}", delete(conversation::delete))
-        .layer(middleware::from_fn(middlewares::auth::auth_guard));
-}
\ No newline at end of file
+        .route("/requests", get(conversation::get_requests))
+        .route("/requests/{id}/accept", post(conversation::accept))
+        .route("/requests/{id}/decline", post(conversation::decline))
+        .route("/requests/{id}/block", post(conversation::block));
+}
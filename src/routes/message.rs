@@ -1,10 +1,29 @@
-use axum::{middleware, routing::get, Router};
+use axum::{routing::{get, patch, put}, Router};
 
-use crate::{handlers::message, middlewares};
+use crate::{handlers::message, state::AppState};
 
 
-pub fn main() -> Router {
+pub fn main() -> Router<AppState> {
     Router::new()
-        .route("/{id}", get(message::get_all))
-        .layer(middleware::from_fn(middlewares::auth::auth_guard))
-}
\ No newline at end of file
+        .route("/search", get(message::search))
+        .route(
+            "/conversation/{conversation_id}",
+            get(message::get_all).post(message::create)
+        )
+        .route("/conversation/{conversation_id}/delivered", patch(message::mark_delivered))
+        .route("/conversation/{conversation_id}/read", patch(message::mark_read))
+        // GET and POST keep the old paths by the conversation id, for the clients still using them.
+        .route(
+            "/{id}",
+            get(message::get_all)
+                .post(message::create)
+                .patch(message::update)
+                .delete(message::delete)
+        )
+        .route("/{conversation_id}/delivered", patch(message::mark_delivered))
+        .route("/{conversation_id}/read", patch(message::mark_read))
+        .route(
+            "/{message_id}/reactions/{emoji}",
+            put(message::add_reaction).delete(message::remove_reaction)
+        )
+}
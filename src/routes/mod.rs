@@ -1,12 +1,26 @@
 use axum::Router;
 
+use crate::state::AppState;
+
 mod user;
 mod conversation;
 mod message;
+mod ws;
+mod attachment;
+mod tus;
+mod block;
+mod contact;
 
-pub fn main() -> Router {
+pub fn main() -> Router<AppState> {
     Router::new()
         .nest("/user", user::main())
+        // the blocks are a list of the user, like its settings.
+        .nest("/user/blocks", block::main())
         .nest("/conversation", conversation::main())
+        .nest("/contact", contact::main())
         .nest("/message", message::main())
-}
\ No newline at end of file
+        .nest("/ws", ws::main())
+        .nest("/attachment", attachment::main())
+        // the tus uploads turn into attachments.
+        .nest("/attachment/uploads", tus::main())
+}
@@ -0,0 +1,21 @@
+use axum::{middleware, routing::{head, post}, Router};
+
+use crate::{handlers::tus, middlewares, state::AppState};
+
+
+pub fn main() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/conversation/{conversation_id}",
+            post(tus::create).options(tus::options)
+        )
+        // the chunks are limited to the rest of the upload while reading them.
+        .route(
+            "/{upload_id}",
+            head(tus::progress)
+                .patch(tus::append)
+                .delete(tus::terminate)
+                .options(tus::options)
+        )
+        .layer(middleware::from_fn(middlewares::tus::check_version))
+}
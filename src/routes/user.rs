@@ -1,28 +1,41 @@
 use axum::{
-    middleware,
+    extract::DefaultBodyLimit,
     routing::{
         delete, 
         get, 
         patch, 
-        post
+        post,
+        put
     },
     Router
 };
 
 use crate::{
     handlers::user,
-    middlewares
+    state::AppState
 };
 
-pub fn main() -> Router {
+pub fn main() -> Router<AppState> {
     Router::new()
         .route("/logout", get(user::logout))
         .route("/refresh", get(user::refresh))
         .route("/update/info", patch(user::update_information))
         .route("/update/pass", patch(user::update_password))
+        // the image is resized, the size of the upload is checked against the config.
+        .route(
+            "/avatar",
+            put(user::update_avatar)
+                .delete(user::delete_avatar)
+                .layer(DefaultBodyLimit::disable())
+        )
+        .route("/avatar/{username}/{size}", get(user::get_avatar))
+        .route(
+            "/settings/privacy",
+            get(user::get_privacy).patch(user::update_privacy)
+        )
         .route("/delete", delete(user::delete))
         .route("/info/{username}", get(user::get_information))
-        .layer(middleware::from_fn(middlewares::auth::auth_guard))
+        .route("/search", get(user::search))
         .route("/login", post(user::login))
         .route("/register", post(user::register))
-}
\ No newline at end of file
+}
@@ -0,0 +1,9 @@
+use axum::{routing::get, Router};
+
+use crate::{handlers::ws, state::AppState};
+
+
+pub fn main() -> Router<AppState> {
+    Router::new()
+        .route("/", get(ws::connect))
+}
@@ -0,0 +1,760 @@
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Postgres, Transaction};
+use tracing::error;
+use uuid::Uuid;
+
+use crate::{
+    error::AppError,
+    modules::{
+        attachment::{Attachment, Upload},
+        user::User
+    },
+    storage::BlobStore,
+    utils::media
+};
+
+// what the uploader $2 already stores, with what its unfinished uploads will add.
+const USED_STORAGE: &str = r#"
+    (
+        SELECT COALESCE(SUM(size), 0) FROM attachments
+        WHERE uploader_id = $2
+    ) + (
+        SELECT COALESCE(SUM(size), 0) FROM uploads
+        WHERE
+            uploader_id = $2 AND
+            expires_at > CURRENT_TIMESTAMP
+    )
+"#;
+// the start of the file the mime type is sniffed from.
+const SNIFF_LENGTH: u64 = 8 * 1024;
+// the files that are not images are hashed in chunks of this size.
+const CHECKSUM_CHUNK_LENGTH: u64 = 8 * 1024 * 1024;
+
+
+// stores the file and records it as a pending attachment of the conversation,
+// the caller must check that the user is a member of the conversation.
+pub async fn create(
+    user: &User,
+    conversation_id: i32,
+    filename: String,
+    data: Vec<u8>,
+    user_storage_quota: i64,
+    store: &dyn BlobStore,
+    pool: &Pool<Postgres>
+) -> Result<Attachment, AppError> {
+    let blob = store_blob(new_storage_key(), data, false, store).await?;
+    return record(
+        user.id,
+        conversation_id,
+        filename,
+        blob,
+        None,
+        user_storage_quota,
+        store,
+        pool
+    ).await;
+}
+
+// a file in the store and what is known about its content.
+struct Blob {
+    storage_key: String,
+    size: i64,
+    checksum: String,
+    mime_type: String,
+    image: Option<ImageBlob>,
+}
+
+struct ImageBlob {
+    width: i32,
+    height: i32,
+    blurhash: String,
+    thumbnail_key: String,
+}
+
+impl Blob {
+    fn keys(self) -> Vec<String> {
+        let mut keys = vec![self.storage_key];
+        if let Some(image) = self.image {
+            keys.push(image.thumbnail_key);
+        }
+        return keys;
+    }
+}
+
+// strips the GPS metadata of the images and stores their thumbnails,
+// `data` is written to the store unless it is `already_stored` and nothing was stripped.
+// the caller deletes an `already_stored` file on error.
+async fn store_blob(
+    storage_key: String,
+    data: Vec<u8>,
+    already_stored: bool,
+    store: &dyn BlobStore
+) -> Result<Blob, AppError> {
+    let (data, stripped, image) = match tokio::task::spawn_blocking(move || {
+        let (data, stripped) = match media::strip_gps(&data) {
+            Ok(Some(stripped)) => (stripped, true),
+            Ok(None) => (data, false),
+            Err(_) => return None
+        };
+        let image = media::inspect_image(&data);
+        return Some((data, stripped, image));
+    }).await {
+        Ok(Some(result)) => result,
+        // the GPS tags may still be in it, the file is not kept.
+        Ok(None) => return Err(AppError::ValidationError(
+            "file: the image metadata can NOT be read".to_string()
+        )),
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    };
+    let (size, checksum, mime_type) = inspect(&data);
+    if !already_stored || stripped {
+        store.put(&storage_key, data).await?;
+    }
+    let image = match image {
+        Some(image) => {
+            let thumbnail_key = format!("{}-thumbnail", storage_key);
+            if let Err(err) = store.put(&thumbnail_key, image.thumbnail).await {
+                delete_blobs(vec![storage_key], store).await;
+                return Err(err);
+            }
+            Some(ImageBlob {
+                width: image.width,
+                height: image.height,
+                blurhash: image.blurhash,
+                thumbnail_key
+            })
+        }
+        None => None
+    };
+    return Ok(Blob {
+        storage_key,
+        size,
+        checksum,
+        mime_type,
+        image
+    });
+}
+
+// size, checksum and the mime type sniffed from the content.
+fn inspect(data: &[u8]) -> (i64, String, String) {
+    let checksum = hex::encode(Sha256::digest(data));
+    let mime_type = sniff_mime_type(&data[..data.len().min(SNIFF_LENGTH as usize)]);
+    return (data.len() as i64, checksum, mime_type);
+}
+
+// `head` is the start of the file, a character cut at its end is still text.
+fn sniff_mime_type(head: &[u8]) -> String {
+    if let Some(kind) = infer::get(head) {
+        return kind.mime_type().to_string();
+    }
+    let is_text = match std::str::from_utf8(head) {
+        Ok(_) => true,
+        Err(err) => err.error_len().is_none()
+    };
+    if is_text {
+        return "text/plain".to_string();
+    }
+    return "application/octet-stream".to_string();
+}
+
+// the uploads of a user are serialized on the user row,
+// so the quota is checked against what the others already recorded.
+// NO KEY UPDATE does not block the rows that reference the user.
+async fn lock_quota(
+    uploader_id: i32,
+    tx: &mut Transaction<'_, Postgres>
+) -> Result<(), sqlx::Error> {
+    sqlx::query(r#"
+        SELECT id FROM users
+        WHERE id = $1
+        FOR NO KEY UPDATE;
+    "#)
+        .bind(uploader_id)
+        .execute(&mut **tx)
+        .await?;
+    return Ok(());
+}
+
+// inserts the row of a file that is already in the store,
+// the file is deleted if the user is over the quota.
+// the `upload` the file comes from is removed with its parts in the same transaction,
+// it is not found when another request completed it or when it expired.
+#[allow(clippy::too_many_arguments)]
+async fn record(
+    uploader_id: i32,
+    conversation_id: i32,
+    filename: String,
+    blob: Blob,
+    upload: Option<&Upload>,
+    user_storage_quota: i64,
+    store: &dyn BlobStore,
+    pool: &Pool<Postgres>
+) -> Result<Attachment, AppError> {
+    let result = async {
+        let mut tx = pool.begin().await?;
+        lock_quota(uploader_id, &mut tx).await?;
+        let part_keys = match upload {
+            Some(upload) => {
+                let part_keys = sqlx::query_scalar::<_, Vec<String>>(r#"
+                    DELETE FROM uploads
+                    WHERE
+                        id = $1 AND
+                        expires_at > CURRENT_TIMESTAMP
+                    RETURNING part_keys;
+                "#)
+                    .bind(upload.id)
+                    .fetch_optional(&mut *tx)
+                    .await?;
+                match part_keys {
+                    Some(part_keys) => part_keys,
+                    None => return Ok::<_, sqlx::Error>(None)
+                }
+            }
+            None => Vec::new()
+        };
+        let query = format!(r#"
+            INSERT INTO attachments (
+                conversation_id,
+                uploader_id,
+                storage_key,
+                filename,
+                mime_type,
+                size,
+                checksum,
+                width,
+                height,
+                blurhash,
+                thumbnail_key
+            )
+            SELECT $1, $2, $3, $4, $5, $6, $7, $9, $10, $11, $12
+            WHERE {} + $6 <= $8
+            RETURNING
+                id,
+                conversation_id,
+                message_id,
+                uploader_id,
+                storage_key,
+                filename,
+                mime_type,
+                size,
+                checksum,
+                width,
+                height,
+                blurhash,
+                thumbnail_key,
+                created_at;
+        "#, USED_STORAGE);
+        let attachment = sqlx::query_as::<_, Attachment>(&query)
+            .bind(conversation_id)
+            .bind(uploader_id)
+            .bind(&blob.storage_key)
+            .bind(&filename)
+            .bind(&blob.mime_type)
+            .bind(blob.size)
+            .bind(&blob.checksum)
+            .bind(user_storage_quota)
+            .bind(blob.image.as_ref().map(|image| image.width))
+            .bind(blob.image.as_ref().map(|image| image.height))
+            .bind(blob.image.as_ref().map(|image| &image.blurhash))
+            .bind(blob.image.as_ref().map(|image| &image.thumbnail_key))
+            .fetch_optional(&mut *tx)
+            .await?;
+        // over the quota the upload is dropped too.
+        tx.commit().await?;
+        return Ok(Some((part_keys, attachment)));
+    }.await;
+    match result {
+        Ok(Some((part_keys, Some(attachment)))) => {
+            delete_blobs(part_keys, store).await;
+            return Ok(attachment);
+        }
+        Ok(Some((part_keys, None))) => {
+            delete_blobs(part_keys, store).await;
+            delete_blobs(blob.keys(), store).await;
+            return Err(AppError::QuotaExceeded);
+        }
+        // the file at the storage key of the upload belongs to the request that completed it.
+        Ok(None) => {
+            if upload.is_some_and(|upload| upload.storage_key != blob.storage_key) {
+                delete_blobs(blob.keys(), store).await;
+            }
+            return Err(AppError::NotFoundData);
+        }
+        Err(err) => {
+            delete_blobs(blob.keys(), store).await;
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    }
+}
+
+// only the members of the conversation can see its attachments.
+pub async fn find(
+    user: &User,
+    id: i32,
+    pool: &Pool<Postgres>
+) -> Result<Attachment, AppError> {
+    let result = sqlx::query_as::<_, Attachment>(r#"
+        SELECT
+            attachments.id,
+            attachments.conversation_id,
+            attachments.message_id,
+            attachments.uploader_id,
+            attachments.storage_key,
+            attachments.filename,
+            attachments.mime_type,
+            attachments.size,
+            attachments.checksum,
+            attachments.width,
+            attachments.height,
+            attachments.blurhash,
+            attachments.thumbnail_key,
+            attachments.created_at
+        FROM attachments
+        JOIN conversation_members member ON
+            member.conversation_id = attachments.conversation_id AND
+            member.user_id = $2
+        WHERE
+            attachments.id = $1 AND (
+                attachments.message_id IS NOT NULL OR
+                attachments.uploader_id = $2
+            );
+    "#)
+        .bind(id)
+        .bind(user.id)
+        .fetch_one(pool)
+        .await;
+    match result {
+        Ok(attachment) => return Ok(attachment),
+        Err(err) => match err {
+            sqlx::Error::RowNotFound => return Err(AppError::NotFoundData),
+            other => {
+                error!("{:#?}", other);
+                return Err(AppError::InternalServerError);
+            }
+        }
+    }
+}
+
+// the rows are already gone, a failure here only leaves a file behind.
+pub async fn delete_blobs(storage_keys: Vec<String>, store: &dyn BlobStore) {
+    for storage_key in storage_keys {
+        if store.delete(&storage_key).await.is_err() {
+            error!("Can not delete the blob '{}'!", storage_key);
+        }
+    }
+}
+
+pub fn new_storage_key() -> String {
+    return Uuid::new_v4().to_string();
+}
+
+// reserves the storage key the client uploads to directly or in chunks through the server,
+// the caller must check that the user is a member of the conversation.
+#[allow(clippy::too_many_arguments)]
+pub async fn create_upload(
+    user: &User,
+    conversation_id: i32,
+    storage_key: String,
+    filename: String,
+    size: i64,
+    resumable: bool,
+    expires_in: std::time::Duration,
+    user_storage_quota: i64,
+    pool: &Pool<Postgres>
+) -> Result<Upload, AppError> {
+    let result = async {
+        let mut tx = pool.begin().await?;
+        lock_quota(user.id, &mut tx).await?;
+        let query = format!(r#"
+            INSERT INTO uploads (
+                conversation_id,
+                uploader_id,
+                storage_key,
+                filename,
+                size,
+                resumable,
+                expires_at
+            )
+            SELECT $1, $2, $3, $4, $5, $8, CURRENT_TIMESTAMP + $6
+            WHERE {} + $5 <= $7
+            RETURNING
+                id,
+                conversation_id,
+                uploader_id,
+                storage_key,
+                filename,
+                size,
+                resumable,
+                upload_offset,
+                part_keys,
+                expires_at;
+        "#, USED_STORAGE);
+        let upload = sqlx::query_as::<_, Upload>(&query)
+            .bind(conversation_id)
+            .bind(user.id)
+            .bind(&storage_key)
+            .bind(&filename)
+            .bind(size)
+            .bind(expires_in)
+            .bind(user_storage_quota)
+            .bind(resumable)
+            .fetch_optional(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        return Ok::<_, sqlx::Error>(upload);
+    }.await;
+    match result {
+        Ok(Some(upload)) => return Ok(upload),
+        Ok(None) => return Err(AppError::QuotaExceeded),
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    }
+}
+
+// turns an upload the client finished into a pending attachment,
+// the size is checked before anything is read and only the images are read whole.
+// the upload keeps its place in the quota until the attachment is recorded,
+// a client that completes too early can still PUT the file and complete again.
+pub async fn complete_upload(
+    user: &User,
+    id: i32,
+    max_attachment_size: i64,
+    user_storage_quota: i64,
+    store: &dyn BlobStore,
+    pool: &Pool<Postgres>
+) -> Result<Attachment, AppError> {
+    let result = sqlx::query_as::<_, Upload>(r#"
+        SELECT
+            id,
+            conversation_id,
+            uploader_id,
+            storage_key,
+            filename,
+            size,
+            resumable,
+            upload_offset,
+            part_keys,
+            expires_at
+        FROM uploads
+        WHERE
+            id = $1 AND
+            uploader_id = $2 AND
+            NOT resumable AND
+            expires_at > CURRENT_TIMESTAMP;
+    "#)
+        .bind(id)
+        .bind(user.id)
+        .fetch_optional(pool)
+        .await;
+    let upload = match result {
+        Ok(Some(upload)) => upload,
+        Ok(None) => return Err(AppError::NotFoundData),
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    };
+    let size = match store.size(&upload.storage_key).await {
+        Ok(size) => size as i64,
+        Err(AppError::NotFoundData) => return Err(AppError::ValidationError(
+            "the file is NOT uploaded!".to_string()
+        )),
+        Err(err) => return Err(err)
+    };
+    if size != upload.size || size > max_attachment_size {
+        delete_blobs(vec![upload.storage_key], store).await;
+        return Err(AppError::ValidationError(
+            "size: the uploaded file does NOT match the declared size".to_string()
+        ));
+    }
+    let blob = match read_uploaded_blob(upload.storage_key.clone(), size, store).await {
+        Ok(blob) => blob,
+        Err(err) => {
+            delete_blobs(vec![upload.storage_key], store).await;
+            return Err(err);
+        }
+    };
+    return record(
+        user.id,
+        upload.conversation_id,
+        upload.filename.clone(),
+        blob,
+        Some(&upload),
+        user_storage_quota,
+        store,
+        pool
+    ).await;
+}
+
+// the images are read whole to strip them and make their thumbnails,
+// the other files are only hashed a chunk at a time.
+async fn read_uploaded_blob(
+    storage_key: String,
+    size: i64,
+    store: &dyn BlobStore
+) -> Result<Blob, AppError> {
+    let head = store.get_range(&storage_key, 0, SNIFF_LENGTH).await?;
+    let mime_type = sniff_mime_type(&head);
+    if media::is_supported_image(&mime_type) {
+        let data = store.get(&storage_key).await?;
+        return store_blob(storage_key, data, true, store).await;
+    }
+    let mut hasher = Sha256::new();
+    let mut start = 0;
+    while start < size as u64 {
+        let chunk = store.get_range(&storage_key, start, CHECKSUM_CHUNK_LENGTH).await?;
+        if chunk.is_empty() {
+            error!("The blob '{}' is shorter than its size!", storage_key);
+            return Err(AppError::InternalServerError);
+        }
+        hasher.update(&chunk);
+        start += chunk.len() as u64;
+    }
+    return Ok(Blob {
+        storage_key,
+        size,
+        checksum: hex::encode(hasher.finalize()),
+        mime_type,
+        image: None
+    });
+}
+
+// only the uploader can see an upload, and only until it expires.
+pub async fn find_resumable_upload(
+    user: &User,
+    id: i32,
+    pool: &Pool<Postgres>
+) -> Result<Upload, AppError> {
+    let result = sqlx::query_as::<_, Upload>(r#"
+        SELECT
+            id,
+            conversation_id,
+            uploader_id,
+            storage_key,
+            filename,
+            size,
+            resumable,
+            upload_offset,
+            part_keys,
+            expires_at
+        FROM uploads
+        WHERE
+            id = $1 AND
+            uploader_id = $2 AND
+            resumable AND
+            expires_at > CURRENT_TIMESTAMP;
+    "#)
+        .bind(id)
+        .bind(user.id)
+        .fetch_optional(pool)
+        .await;
+    match result {
+        Ok(Some(upload)) => return Ok(upload),
+        Ok(None) => return Err(AppError::NotFoundData),
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    }
+}
+
+// stores the chunk at `upload_offset` and moves the offset past it,
+// the upload turns into a pending attachment once its last chunk arrives.
+pub async fn append_upload(
+    user: &User,
+    upload: Upload,
+    data: Vec<u8>,
+    expires_in: std::time::Duration,
+    user_storage_quota: i64,
+    store: &dyn BlobStore,
+    pool: &Pool<Postgres>
+) -> Result<(Upload, Option<Attachment>), AppError> {
+    if upload.upload_offset + data.len() as i64 > upload.size {
+        return Err(AppError::PayloadTooLarge);
+    }
+    // every chunk gets its own key, a chunk that loses
+    // a race for the same offset can not overwrite the winner.
+    let part_key = format!("{}-part-{}", upload.storage_key, Uuid::new_v4());
+    let length = data.len() as i64;
+    if length > 0 {
+        store.put(&part_key, data).await?;
+    }
+    let result = sqlx::query_as::<_, Upload>(r#"
+        UPDATE uploads
+        SET
+            upload_offset = upload_offset + $3,
+            part_keys = CASE
+                WHEN $3 > 0 THEN array_append(part_keys, $4)
+                ELSE part_keys
+            END,
+            expires_at = CURRENT_TIMESTAMP + $5
+        WHERE
+            id = $1 AND
+            upload_offset = $2 AND
+            expires_at > CURRENT_TIMESTAMP
+        RETURNING
+            id,
+            conversation_id,
+            uploader_id,
+            storage_key,
+            filename,
+            size,
+            resumable,
+            upload_offset,
+            part_keys,
+            expires_at;
+    "#)
+        .bind(upload.id)
+        .bind(upload.upload_offset)
+        .bind(length)
+        .bind(&part_key)
+        .bind(expires_in)
+        .fetch_optional(pool)
+        .await;
+    let upload = match result {
+        Ok(Some(upload)) => upload,
+        Ok(None) => {
+            if length > 0 {
+                delete_blobs(vec![part_key], store).await;
+            }
+            return Err(AppError::Conflict);
+        }
+        Err(err) => {
+            if length > 0 {
+                delete_blobs(vec![part_key], store).await;
+            }
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    };
+    if upload.upload_offset < upload.size {
+        return Ok((upload, None));
+    }
+    let attachment = finish_upload(user, upload.id, user_storage_quota, store, pool).await?;
+    return Ok((upload, Some(attachment)));
+}
+
+// joins the chunks of a finished resumable upload into the file of the attachment,
+// the upload and its chunks are kept until the attachment is recorded.
+// every attempt joins them under a new key, two of them never write to the same file.
+async fn finish_upload(
+    user: &User,
+    id: i32,
+    user_storage_quota: i64,
+    store: &dyn BlobStore,
+    pool: &Pool<Postgres>
+) -> Result<Attachment, AppError> {
+    let result = sqlx::query_as::<_, Upload>(r#"
+        SELECT
+            id,
+            conversation_id,
+            uploader_id,
+            storage_key,
+            filename,
+            size,
+            resumable,
+            upload_offset,
+            part_keys,
+            expires_at
+        FROM uploads
+        WHERE
+            id = $1 AND
+            uploader_id = $2 AND
+            resumable AND
+            upload_offset = size AND
+            expires_at > CURRENT_TIMESTAMP;
+    "#)
+        .bind(id)
+        .bind(user.id)
+        .fetch_optional(pool)
+        .await;
+    let upload = match result {
+        Ok(Some(upload)) => upload,
+        Ok(None) => return Err(AppError::NotFoundData),
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    };
+    let storage_key = new_storage_key();
+    if let Err(err) = store.compose(&storage_key, &upload.part_keys).await {
+        delete_blobs(vec![storage_key], store).await;
+        return Err(err);
+    }
+    let blob = match read_uploaded_blob(storage_key.clone(), upload.size, store).await {
+        Ok(blob) => blob,
+        Err(err) => {
+            delete_blobs(vec![storage_key], store).await;
+            return Err(err);
+        }
+    };
+    return record(
+        user.id,
+        upload.conversation_id,
+        upload.filename.clone(),
+        blob,
+        Some(&upload),
+        user_storage_quota,
+        store,
+        pool
+    ).await;
+}
+
+// drops a resumable upload and the chunks it received.
+pub async fn delete_upload(
+    user: &User,
+    id: i32,
+    store: &dyn BlobStore,
+    pool: &Pool<Postgres>
+) -> Result<(), AppError> {
+    let result = sqlx::query_scalar::<_, Vec<String>>(r#"
+        DELETE FROM uploads
+        WHERE
+            id = $1 AND
+            uploader_id = $2 AND
+            resumable
+        RETURNING part_keys;
+    "#)
+        .bind(id)
+        .bind(user.id)
+        .fetch_optional(pool)
+        .await;
+    match result {
+        Ok(Some(part_keys)) => {
+            delete_blobs(part_keys, store).await;
+            return Ok(());
+        }
+        Ok(None) => return Err(AppError::NotFoundData),
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    }
+}
+
+// the uploads the clients abandoned, with whatever they left in the store.
+pub async fn delete_expired_uploads(store: &dyn BlobStore, pool: &Pool<Postgres>) {
+    let result = sqlx::query_as::<_, (String, Vec<String>)>(r#"
+        DELETE FROM uploads
+        WHERE expires_at <= CURRENT_TIMESTAMP
+        RETURNING storage_key, part_keys;
+    "#)
+        .fetch_all(pool)
+        .await;
+    match result {
+        Ok(rows) => {
+            for (storage_key, part_keys) in rows {
+                delete_blobs(part_keys, store).await;
+                // a presigned upload may have reached the store.
+                delete_blobs(vec![storage_key], store).await;
+            }
+        }
+        Err(err) => error!("{:#?}", err)
+    }
+}
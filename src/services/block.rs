@@ -0,0 +1,180 @@
+use sqlx::{Pool, Postgres};
+use tracing::error;
+
+use crate::{
+    error::AppError,
+    modules::{block::BlockedUser, user::User}
+};
+
+
+// blocking is allowed even if the other user already blocked this one,
+// so the user is looked up without `services::user::find`.
+// the contact or contact request between the two users is removed.
+pub async fn create(
+    user: &User,
+    username: String,
+    pool: &Pool<Postgres>
+) -> Result<BlockedUser, AppError> {
+    if username == user.username {
+        return Err(AppError::BadRequest);
+    }
+    let result = sqlx::query_as::<_, BlockedUser>(r#"
+        WITH blocked AS (
+            SELECT id, username, name FROM users
+            WHERE username = $2
+        ), inserted AS (
+            INSERT INTO user_blocks (blocker_id, blocked_id)
+            SELECT $1, id FROM blocked
+            ON CONFLICT (blocker_id, blocked_id) DO NOTHING
+            RETURNING created_at
+        ), removed AS (
+            DELETE FROM contacts
+            USING blocked
+            WHERE
+                (requester_id = $1 AND addressee_id = blocked.id) OR
+                (requester_id = blocked.id AND addressee_id = $1)
+        )
+        SELECT
+            blocked.id,
+            blocked.username,
+            blocked.name,
+            COALESCE(
+                (SELECT created_at FROM inserted),
+                (
+                    SELECT created_at FROM user_blocks
+                    WHERE
+                        blocker_id = $1 AND
+                        blocked_id = blocked.id
+                )
+            ) as blocked_at
+        FROM blocked;
+    "#)
+        .bind(user.id)
+        .bind(&username)
+        .fetch_optional(pool)
+        .await;
+    match result {
+        Ok(Some(blocked_user)) => return Ok(blocked_user),
+        Ok(None) => return Err(AppError::NotFoundUser),
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    }
+}
+
+pub async fn delete(
+    user: &User,
+    username: String,
+    pool: &Pool<Postgres>
+) -> Result<(), AppError> {
+    let result = sqlx::query(r#"
+        DELETE FROM user_blocks
+        USING users
+        WHERE
+            user_blocks.blocker_id = $1 AND
+            user_blocks.blocked_id = users.id AND
+            users.username = $2;
+    "#)
+        .bind(user.id)
+        .bind(&username)
+        .execute(pool)
+        .await;
+    match result {
+        Ok(result) => {
+            if result.rows_affected() == 0 {
+                return Err(AppError::NotFoundData);
+            }
+            return Ok(());
+        }
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    }
+}
+
+pub async fn get_all(
+    user: &User,
+    pool: &Pool<Postgres>
+) -> Result<Vec<BlockedUser>, AppError> {
+    let result = sqlx::query_as::<_, BlockedUser>(r#"
+        SELECT
+            users.id,
+            users.username,
+            users.name,
+            user_blocks.created_at as blocked_at
+        FROM user_blocks
+        JOIN users ON users.id = user_blocks.blocked_id
+        WHERE user_blocks.blocker_id = $1
+        ORDER BY user_blocks.created_at DESC;
+    "#)
+        .bind(user.id)
+        .fetch_all(pool)
+        .await;
+    match result {
+        Ok(blocked_users) => return Ok(blocked_users),
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    }
+}
+
+// true if either user blocked the other.
+pub async fn exists_between(
+    user_id: i32,
+    other_id: i32,
+    pool: &Pool<Postgres>
+) -> Result<bool, AppError> {
+    let result = sqlx::query_scalar::<_, bool>(r#"
+        SELECT EXISTS (
+            SELECT 1 FROM user_blocks
+            WHERE
+                (blocker_id = $1 AND blocked_id = $2) OR
+                (blocker_id = $2 AND blocked_id = $1)
+        );
+    "#)
+        .bind(user_id)
+        .bind(other_id)
+        .fetch_one(pool)
+        .await;
+    match result {
+        Ok(exists) => return Ok(exists),
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    }
+}
+
+// true if the user and another member of the conversation blocked each other.
+pub async fn exists_in_conversation(
+    conversation_id: i32,
+    user_id: i32,
+    pool: &Pool<Postgres>
+) -> Result<bool, AppError> {
+    let result = sqlx::query_scalar::<_, bool>(r#"
+        SELECT EXISTS (
+            SELECT 1
+            FROM conversation_members peer
+            JOIN user_blocks ON
+                (user_blocks.blocker_id = $2 AND user_blocks.blocked_id = peer.user_id) OR
+                (user_blocks.blocker_id = peer.user_id AND user_blocks.blocked_id = $2)
+            WHERE
+                peer.conversation_id = $1 AND
+                peer.user_id <> $2
+        );
+    "#)
+        .bind(conversation_id)
+        .bind(user_id)
+        .fetch_one(pool)
+        .await;
+    match result {
+        Ok(exists) => return Ok(exists),
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    }
+}
@@ -0,0 +1,326 @@
+use sqlx::{Pool, Postgres};
+use tracing::error;
+
+use crate::{
+    error::AppError,
+    modules::{
+        contact::{Contact, ContactRequest, ContactStatus},
+        user::User
+    },
+    presence::Presence,
+    services
+};
+
+
+// returns the other user id, the status after the request
+// and `true` if something changed (a request was sent or accepted).
+// a request to a user that already sent one to this user accepts it.
+pub async fn send_request(
+    user: &User,
+    username: String,
+    pool: &Pool<Postgres>
+) -> Result<(i32, ContactStatus, bool), AppError> {
+    if username == user.username {
+        return Err(AppError::BadRequest);
+    }
+    let peer = services::user::find(username.clone(), user.id, pool).await?;
+    loop {
+        match get_status(user.id, peer.id, pool).await? {
+            ContactStatus::Contact => return Err(AppError::Conflict),
+            ContactStatus::RequestSent => return Ok((peer.id, ContactStatus::RequestSent, false)),
+            ContactStatus::RequestReceived => {
+                match accept(user, username.clone(), pool).await {
+                    Ok(_) => return Ok((peer.id, ContactStatus::Contact, true)),
+                    // the request was cancelled meanwhile, try again.
+                    Err(AppError::NotFoundData) => continue,
+                    Err(err) => return Err(err)
+                }
+            }
+            ContactStatus::None => {}
+        }
+        let insert_result = sqlx::query(r#"
+            INSERT INTO contacts (requester_id, addressee_id)
+            VALUES ($1, $2)
+            ON CONFLICT DO NOTHING;
+        "#)
+            .bind(user.id)
+            .bind(peer.id)
+            .execute(pool)
+            .await;
+        match insert_result {
+            Ok(result) => {
+                if result.rows_affected() == 1 {
+                    return Ok((peer.id, ContactStatus::RequestSent, true));
+                }
+                // the other user sent a request meanwhile, try again.
+            }
+            Err(err) => {
+                error!("{:#?}", err);
+                return Err(AppError::InternalServerError);
+            }
+        }
+    }
+}
+
+// returns the id of the user that sent the request,
+// a pending conversation between the two users is accepted too.
+pub async fn accept(
+    user: &User,
+    username: String,
+    pool: &Pool<Postgres>
+) -> Result<i32, AppError> {
+    let result = sqlx::query_scalar::<_, i32>(r#"
+        WITH accepted AS (
+            UPDATE contacts
+            SET accepted_at = CURRENT_TIMESTAMP
+            FROM users
+            WHERE
+                users.username = $2 AND
+                contacts.requester_id = users.id AND
+                contacts.addressee_id = $1 AND
+                contacts.accepted_at IS NULL
+            RETURNING contacts.requester_id
+        ), conversation AS (
+            UPDATE conversations
+            SET pending = FALSE
+            FROM accepted
+            WHERE
+                conversations.pending AND
+                LEAST(user1_id, user2_id)    = LEAST($1, accepted.requester_id) AND
+                GREATEST(user1_id, user2_id) = GREATEST($1, accepted.requester_id)
+        )
+        SELECT requester_id FROM accepted;
+    "#)
+        .bind(user.id)
+        .bind(&username)
+        .fetch_optional(pool)
+        .await;
+    match result {
+        Ok(Some(requester_id)) => return Ok(requester_id),
+        Ok(None) => return Err(AppError::NotFoundData),
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    }
+}
+
+// `incoming` declines a request sent to the user, otherwise cancels one sent by the user.
+pub async fn delete_request(
+    user: &User,
+    username: String,
+    incoming: bool,
+    pool: &Pool<Postgres>
+) -> Result<(), AppError> {
+    let result = sqlx::query(r#"
+        DELETE FROM contacts
+        USING users
+        WHERE
+            users.username = $2 AND
+            contacts.accepted_at IS NULL AND
+            CASE WHEN $3
+                THEN contacts.requester_id = users.id AND contacts.addressee_id = $1
+                ELSE contacts.requester_id = $1 AND contacts.addressee_id = users.id
+            END;
+    "#)
+        .bind(user.id)
+        .bind(&username)
+        .bind(incoming)
+        .execute(pool)
+        .await;
+    match result {
+        Ok(result) => {
+            if result.rows_affected() == 0 {
+                return Err(AppError::NotFoundData);
+            }
+            return Ok(());
+        }
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    }
+}
+
+// returns the id of the removed contact.
+pub async fn delete(
+    user: &User,
+    username: String,
+    pool: &Pool<Postgres>
+) -> Result<i32, AppError> {
+    let result = sqlx::query_scalar::<_, i32>(r#"
+        DELETE FROM contacts
+        USING users
+        WHERE
+            users.username = $2 AND
+            contacts.accepted_at IS NOT NULL AND (
+                (contacts.requester_id = $1 AND contacts.addressee_id = users.id) OR
+                (contacts.requester_id = users.id AND contacts.addressee_id = $1)
+            )
+        RETURNING users.id;
+    "#)
+        .bind(user.id)
+        .bind(&username)
+        .fetch_optional(pool)
+        .await;
+    match result {
+        Ok(Some(contact_id)) => return Ok(contact_id),
+        Ok(None) => return Err(AppError::NotFoundData),
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    }
+}
+
+pub async fn get_all(
+    user_id: i32,
+    presence: &Presence,
+    pool: &Pool<Postgres>
+) -> Result<Vec<Contact>, AppError> {
+    let result = sqlx::query_as::<_, Contact>(r#"
+        SELECT
+            users.id,
+            users.username,
+            users.name,
+            CASE WHEN users.gender_visibility <> 'nobody'
+                THEN users.gender
+            END as gender,
+            CASE WHEN users.last_seen_visibility <> 'nobody'
+                THEN users.last_seen_at
+            END as last_seen_at,
+            users.last_seen_visibility <> 'nobody' as presence_visible,
+            contacts.accepted_at as since
+        FROM contacts
+        JOIN users ON users.id = (
+            CASE WHEN contacts.requester_id = $1
+                THEN contacts.addressee_id
+                ELSE contacts.requester_id
+            END
+        )
+        WHERE
+            (contacts.requester_id = $1 OR contacts.addressee_id = $1) AND
+            contacts.accepted_at IS NOT NULL
+        ORDER BY users.name, users.id;
+    "#)
+        .bind(user_id)
+        .fetch_all(pool)
+        .await;
+    match result {
+        Ok(mut contacts) => {
+            for contact in &mut contacts {
+                contact.online = contact.presence_visible && presence.is_online(contact.id);
+            }
+            return Ok(contacts);
+        }
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    }
+}
+
+// the pending requests sent to and by the user, newest first.
+pub async fn get_requests(
+    user_id: i32,
+    pool: &Pool<Postgres>
+) -> Result<Vec<ContactRequest>, AppError> {
+    let result = sqlx::query_as::<_, ContactRequest>(r#"
+        SELECT
+            users.id,
+            users.username,
+            users.name,
+            contacts.addressee_id = $1 as incoming,
+            contacts.created_at
+        FROM contacts
+        JOIN users ON users.id = (
+            CASE WHEN contacts.requester_id = $1
+                THEN contacts.addressee_id
+                ELSE contacts.requester_id
+            END
+        )
+        WHERE
+            (contacts.requester_id = $1 OR contacts.addressee_id = $1) AND
+            contacts.accepted_at IS NULL
+        ORDER BY contacts.created_at DESC;
+    "#)
+        .bind(user_id)
+        .fetch_all(pool)
+        .await;
+    match result {
+        Ok(requests) => return Ok(requests),
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    }
+}
+
+pub async fn get_status(
+    user_id: i32,
+    other_id: i32,
+    pool: &Pool<Postgres>
+) -> Result<ContactStatus, AppError> {
+    let result = sqlx::query_as::<_, (i32, bool)>(r#"
+        SELECT
+            requester_id,
+            accepted_at IS NOT NULL
+        FROM contacts
+        WHERE
+            (requester_id = $1 AND addressee_id = $2) OR
+            (requester_id = $2 AND addressee_id = $1);
+    "#)
+        .bind(user_id)
+        .bind(other_id)
+        .fetch_optional(pool)
+        .await;
+    match result {
+        Ok(None) => return Ok(ContactStatus::None),
+        Ok(Some((_, true))) => return Ok(ContactStatus::Contact),
+        Ok(Some((requester_id, false))) => {
+            if requester_id == user_id {
+                return Ok(ContactStatus::RequestSent);
+            }
+            return Ok(ContactStatus::RequestReceived);
+        }
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    }
+}
+
+pub async fn are_contacts(
+    user_id: i32,
+    other_id: i32,
+    pool: &Pool<Postgres>
+) -> Result<bool, AppError> {
+    return Ok(get_status(user_id, other_id, pool).await? == ContactStatus::Contact);
+}
+
+pub async fn get_contact_ids(
+    user_id: i32,
+    pool: &Pool<Postgres>
+) -> Result<Vec<i32>, AppError> {
+    let result = sqlx::query_scalar::<_, i32>(r#"
+        SELECT
+            CASE WHEN requester_id = $1
+                THEN addressee_id
+                ELSE requester_id
+            END
+        FROM contacts
+        WHERE
+            (requester_id = $1 OR addressee_id = $1) AND
+            accepted_at IS NOT NULL;
+    "#)
+        .bind(user_id)
+        .fetch_all(pool)
+        .await;
+    match result {
+        Ok(contact_ids) => return Ok(contact_ids),
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    }
+}
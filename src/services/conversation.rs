@@ -1,89 +1,296 @@
 use sqlx::{Pool, Postgres};
 use tracing::error;
 
-use crate::{error::AppError, modules::{conversation::Conversation, user::User}};
+use crate::{
+    error::AppError,
+    modules::{
+        conversation::{Conversation, ConversationView, ListQuery},
+        user::{User, Visibility, DELETED_USER_NAME}
+    },
+    presence::Presence,
+    services,
+    storage::BlobStore
+};
 
+const DEFAULT_PAGE_SIZE: i64 = 20;
+const MAX_PAGE_SIZE: i64 = 100;
+// the conversation can be deleted between the insert and the select of `create`.
+const CREATE_ATTEMPTS: usize = 3;
 
 
+// returns the conversation between the two users and `true` if it was just created,
+// there is only one conversation for each pair of users.
+// a new conversation with a user that is not a contact starts as a message request.
 pub async fn create(
     username: String,
     user: User,
     pool: &Pool<Postgres>
-) -> Result<Conversation, AppError> {
+) -> Result<(Conversation, bool), AppError> {
     if username == user.username {
         return Err(AppError::BadRequest);
     }
-    let result = sqlx::query_as::<_, Conversation>(r#"
-        INSERT INTO conversations (user1_id, user2_id)
-        VALUES (
-            $1, 
-            (
-                SELECT 
-                    id as user2_id
-                FROM users
-                WHERE 
-                    username = $2
-            )
-        )
-        RETURNING
-            id,
-            user1_id,
-            user2_id,
-            last_message,
-            to_char(created_at at time zone 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as created_at, 
-            to_char(updated_at at time zone 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as updated_at;
-    "#)
-        .bind(user.id)
-        .bind(&username)
-        .fetch_one(pool)
-        .await;
-    match result {
-        Ok(conversation) => return Ok(conversation),
-        Err(err) => match err {
-            sqlx::Error::Database(e) => {
-                if let Some(err_code) = e.code() {
-                    if err_code == "23502" {
-                        return Err(AppError::NotFoundUser);
-                    }
+    let peer = match services::user::find(username, user.id, pool).await {
+        Ok(peer) => peer,
+        Err(err) => return Err(err)
+    };
+    let is_contact = services::contact::are_contacts(user.id, peer.id, pool).await?;
+    // the existing conversation is returned even when the peer no longer allows new ones.
+    let allowed = match peer.conversation_visibility {
+        Visibility::Everyone => true,
+        Visibility::Contacts => is_contact,
+        Visibility::Nobody => false
+    };
+    for _ in 0..CREATE_ATTEMPTS {
+        if allowed {
+            let insert_result = sqlx::query_as::<_, Conversation>(r#"
+                WITH inserted AS (
+                    INSERT INTO conversations (user1_id, user2_id, pending)
+                    VALUES ($1, $2, $3)
+                    ON CONFLICT (LEAST(user1_id, user2_id), GREATEST(user1_id, user2_id))
+                    WHERE
+                        user1_id IS NOT NULL AND
+                        user2_id IS NOT NULL
+                    DO NOTHING
+                    RETURNING
+                        id,
+                        user1_id,
+                        user2_id,
+                        pending,
+                        last_message,
+                        created_at,
+                        updated_at
+                ), members AS (
+                    INSERT INTO conversation_members (conversation_id, user_id)
+                    SELECT id, UNNEST(ARRAY[$1, $2]) FROM inserted
+                )
+                SELECT * FROM inserted;
+            "#)
+                .bind(user.id)
+                .bind(peer.id)
+                .bind(!is_contact)
+                .fetch_optional(pool)
+                .await;
+            match insert_result {
+                Ok(Some(conversation)) => return Ok((conversation, true)),
+                Ok(None) => {}
+                Err(err) => {
+                    error!("{:#?}", err);
+                    return Err(AppError::InternalServerError);
                 }
-                error!("{:#?}", e);
-                return Err(AppError::InternalServerError);
             }
-            other => {
-                error!("{:#?}", other);
+        }
+        let find_result = sqlx::query_as::<_, Conversation>(r#"
+            SELECT
+                id,
+                user1_id,
+                user2_id,
+                pending,
+                last_message,
+                created_at,
+                updated_at
+            FROM conversations
+            WHERE
+                LEAST(user1_id, user2_id)    = LEAST($1, $2) AND
+                GREATEST(user1_id, user2_id) = GREATEST($1, $2) AND
+                user1_id IS NOT NULL AND
+                user2_id IS NOT NULL;
+        "#)
+            .bind(user.id)
+            .bind(peer.id)
+            .fetch_optional(pool)
+            .await;
+        match find_result {
+            Ok(Some(conversation)) => return Ok((conversation, false)),
+            Ok(None) => {
+                if !allowed {
+                    return Err(AppError::Forbidden);
+                }
+                // the conversation was deleted between the two queries, try again.
+                continue;
+            }
+            Err(err) => {
+                error!("{:#?}", err);
                 return Err(AppError::InternalServerError);
             }
         }
     }
+    error!("Can not get or create the conversation after {} attempts!", CREATE_ATTEMPTS);
+    return Err(AppError::InternalServerError);
 }
 
+// `requests` lists the message requests received by the user instead of the conversations.
 pub async fn get_all(
     user_id: i32,
+    list_query: ListQuery,
+    requests: bool,
+    presence: &Presence,
     pool: &Pool<Postgres>
-) -> Result<Vec<Conversation>, AppError> {
-    let result = sqlx::query_as::<_, Conversation>(r#"
-        SELECT
-            id,
-            user1_id,
-            user2_id,
-            last_message,
-            to_char(created_at at time zone 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as created_at, 
-            to_char(updated_at at time zone 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as updated_at
-        FROM conversations
-        WHERE 
-            user1_id = $1 OR
-            user2_id = $1;
+) -> Result<Vec<ConversationView>, AppError> {
+    let limit = list_query.limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let result = sqlx::query_as::<_, ConversationView>(r#"
+        SELECT * FROM (
+            SELECT
+                conversations.id,
+                peer.id as peer_id,
+                peer.username as peer_username,
+                COALESCE(peer.name, $2) as peer_name,
+                CASE WHEN peer.gender_visibility = 'everyone' OR (
+                    peer.gender_visibility = 'contacts' AND
+                    EXISTS (
+                        SELECT 1 FROM contacts
+                        WHERE
+                            contacts.accepted_at IS NOT NULL AND (
+                                (requester_id = $1 AND addressee_id = peer.id) OR
+                                (requester_id = peer.id AND addressee_id = $1)
+                            )
+                    )
+                ) THEN peer.gender END as peer_gender,
+                conversations.pending,
+                -- hidden from everyone when one of them blocked the other.
+                COALESCE(presence.visible, FALSE) as peer_presence_visible,
+                CASE WHEN presence.visible
+                    THEN peer.last_seen_at
+                END as peer_last_seen_at,
+                last.id as last_message_id,
+                CASE WHEN last.deleted_at IS NULL THEN last.body END as last_message,
+                last.deleted_at IS NOT NULL as last_message_deleted,
+                last.sender_id as last_message_sender_id,
+                last.created_at as last_message_at,
+                (
+                    SELECT COUNT(*)
+                    FROM messages
+                    WHERE
+                        messages.conversation_id = conversations.id AND
+                        messages.sender_id IS DISTINCT FROM $1 AND
+                        messages.id > COALESCE(member.last_read_message_id, 0) AND
+                        messages.deleted_at IS NULL AND
+                        NOT EXISTS (
+                            SELECT 1 FROM hidden_messages
+                            WHERE
+                                hidden_messages.message_id = messages.id AND
+                                hidden_messages.user_id = $1
+                        )
+                ) as unread_count,
+                -- truncated to the precision of the serialized cursor.
+                date_trunc(
+                    'milliseconds',
+                    COALESCE(last.created_at, conversations.created_at)
+                ) as last_activity_at,
+                conversations.created_at
+            FROM conversations
+            JOIN conversation_members member ON
+                member.conversation_id = conversations.id AND
+                member.user_id = $1
+            LEFT JOIN users peer ON peer.id = (
+                CASE WHEN conversations.user1_id = $1
+                    THEN conversations.user2_id
+                    ELSE conversations.user1_id
+                END
+            )
+            CROSS JOIN LATERAL (
+                SELECT EXISTS (
+                    SELECT 1 FROM user_blocks
+                    WHERE
+                        (blocker_id = $1 AND blocked_id = peer.id) OR
+                        (blocker_id = peer.id AND blocked_id = $1)
+                ) as is_blocked
+            ) blocked
+            CROSS JOIN LATERAL (
+                SELECT
+                    NOT blocked.is_blocked AND (
+                        peer.last_seen_visibility = 'everyone' OR (
+                            peer.last_seen_visibility = 'contacts' AND
+                            EXISTS (
+                                SELECT 1 FROM contacts
+                                WHERE
+                                    contacts.accepted_at IS NOT NULL AND (
+                                        (requester_id = $1 AND addressee_id = peer.id) OR
+                                        (requester_id = peer.id AND addressee_id = $1)
+                                    )
+                            )
+                        )
+                    ) as visible
+            ) presence
+            LEFT JOIN LATERAL (
+                SELECT
+                    id,
+                    body,
+                    sender_id,
+                    created_at,
+                    deleted_at
+                FROM messages
+                WHERE
+                    messages.conversation_id = conversations.id AND
+                    NOT EXISTS (
+                        SELECT 1 FROM hidden_messages
+                        WHERE
+                            hidden_messages.message_id = messages.id AND
+                            hidden_messages.user_id = $1
+                    )
+                ORDER BY id DESC
+                LIMIT 1
+            ) last ON TRUE
+            WHERE
+                CASE WHEN $6
+                    THEN conversations.pending AND conversations.user2_id = $1
+                    ELSE NOT conversations.pending OR conversations.user1_id = $1
+                END
+        ) AS list
+        WHERE
+            $3::TIMESTAMPTZ IS NULL OR
+            (last_activity_at, id) < ($3, COALESCE($4, 2147483647))
+        ORDER BY
+            last_activity_at DESC,
+            id DESC
+        LIMIT $5;
     "#)
         .bind(user_id)
+        .bind(DELETED_USER_NAME)
+        .bind(list_query.before)
+        .bind(list_query.before_id)
+        .bind(limit)
+        .bind(requests)
         .fetch_all(pool)
         .await;
     match result {
-        Ok(conversations) => {
-            if conversations.len() < 1 {
+        Ok(mut conversations) => {
+            for conversation in &mut conversations {
+                conversation.peer_online = conversation.peer_presence_visible &&
+                    conversation.peer_id.is_some_and(|peer_id| presence.is_online(peer_id));
+            }
+            return Ok(conversations);
+        }
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    }
+}
+
+// returns the ids of all the members of the conversation,
+// `NotFoundData` if the user is not one of them.
+pub async fn get_member_ids(
+    id: i32,
+    user_id: i32,
+    pool: &Pool<Postgres>
+) -> Result<Vec<i32>, AppError> {
+    let result = sqlx::query_scalar::<_, i32>(r#"
+        SELECT user_id
+        FROM conversation_members
+        WHERE conversation_id = $1;
+    "#)
+        .bind(id)
+        .fetch_all(pool)
+        .await;
+    match result {
+        Ok(member_ids) => {
+            if !member_ids.contains(&user_id) {
                 return Err(AppError::NotFoundData);
             }
-            return Ok(conversations)
-        },
+            return Ok(member_ids);
+        }
         Err(err) => {
             error!("{:#?}", err);
             return Err(AppError::InternalServerError);
@@ -94,25 +301,131 @@ pub async fn get_all(
 pub async fn delete(
     id: i32,
     user_id: i32,
+    store: &dyn BlobStore,
     pool: &Pool<Postgres>
 ) -> Result<(), AppError> {
     // delete the conversation only if user1_id == user_id
-    // this means that this user is the creatoer of the conversation.
-    let result = sqlx::query(r#"
-        DELETE FROM conversations
+    // this means that this user is the creatoer of the conversation,
+    // or if the creator deleted the account, the other user can delete it.
+    return remove(id, user_id, false, false, store, pool).await;
+}
+
+pub async fn accept(
+    id: i32,
+    user_id: i32,
+    pool: &Pool<Postgres>
+) -> Result<Conversation, AppError> {
+    let result = sqlx::query_as::<_, Conversation>(r#"
+        UPDATE conversations
+        SET
+            pending    = FALSE,
+            updated_at = CURRENT_TIMESTAMP
         WHERE
-            id       = $1 AND
-            user1_id = $2;
+            id = $1 AND
+            user2_id = $2 AND
+            pending
+        RETURNING
+            id,
+            user1_id,
+            user2_id,
+            pending,
+            last_message,
+            created_at,
+            updated_at;
+    "#)
+        .bind(id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await;
+    match result {
+        Ok(Some(conversation)) => return Ok(conversation),
+        Ok(None) => return Err(AppError::NotFoundData),
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    }
+}
+
+// deletes a message request received by the user,
+// with `block` the initiator is blocked in the same statement.
+pub async fn decline(
+    id: i32,
+    user_id: i32,
+    block: bool,
+    store: &dyn BlobStore,
+    pool: &Pool<Postgres>
+) -> Result<(), AppError> {
+    return remove(id, user_id, true, block, store, pool).await;
+}
+
+// with `request` only a pending conversation received by the user is deleted,
+// otherwise only one created by the user.
+// the attachments rows are deleted by the cascade,
+// their files are collected here to be deleted too.
+async fn remove(
+    id: i32,
+    user_id: i32,
+    request: bool,
+    block: bool,
+    store: &dyn BlobStore,
+    pool: &Pool<Postgres>
+) -> Result<(), AppError> {
+    let result = sqlx::query_as::<_, (i32, Option<String>)>(r#"
+        WITH deleted AS (
+            DELETE FROM conversations
+            WHERE
+                id = $1 AND
+                CASE WHEN $4 THEN
+                    user2_id = $2 AND
+                    pending
+                ELSE
+                    user1_id = $2 OR (
+                        user1_id IS NULL AND
+                        user2_id = $2
+                    )
+                END
+            RETURNING id, user1_id
+        ), blocked AS (
+            INSERT INTO user_blocks (blocker_id, blocked_id)
+            SELECT $2, user1_id FROM deleted
+            WHERE
+                $3 AND
+                user1_id IS NOT NULL
+            ON CONFLICT (blocker_id, blocked_id) DO NOTHING
+        ), removed AS (
+            DELETE FROM contacts
+            USING deleted
+            WHERE
+                $3 AND (
+                    (requester_id = $2 AND addressee_id = deleted.user1_id) OR
+                    (requester_id = deleted.user1_id AND addressee_id = $2)
+                )
+        )
+        SELECT
+            deleted.id,
+            blobs.storage_key
+        FROM deleted
+        LEFT JOIN attachments ON attachments.conversation_id = deleted.id
+        LEFT JOIN LATERAL unnest(
+            ARRAY[attachments.storage_key, attachments.thumbnail_key]
+        ) AS blobs(storage_key) ON TRUE;
     "#)
         .bind(id)
         .bind(user_id)
-        .execute(pool)
+        .bind(block)
+        .bind(request)
+        .fetch_all(pool)
         .await;
     match result {
-        Ok(data) => {
-            if data.rows_affected() < 1 {
+        Ok(rows) => {
+            if rows.is_empty() {
                 return Err(AppError::NotFoundData);
             }
+            services::attachment::delete_blobs(
+                rows.into_iter().filter_map(|(_, storage_key)| storage_key).collect(),
+                store
+            ).await;
             return Ok(());
         },
         Err(err) => {
@@ -120,4 +433,39 @@ pub async fn delete(
             return Err(AppError::InternalServerError);
         }
     }
-}
\ No newline at end of file
+}
+
+// the users that share an accepted conversation with the user,
+// without the ones blocked by or blocking the user.
+pub async fn get_peer_ids(
+    user_id: i32,
+    pool: &Pool<Postgres>
+) -> Result<Vec<i32>, AppError> {
+    let result = sqlx::query_scalar::<_, i32>(r#"
+        SELECT DISTINCT peer.user_id
+        FROM conversation_members member
+        JOIN conversations ON conversations.id = member.conversation_id
+        JOIN conversation_members peer ON
+            peer.conversation_id = member.conversation_id AND
+            peer.user_id <> member.user_id
+        WHERE
+            member.user_id = $1 AND
+            NOT conversations.pending AND
+            NOT EXISTS (
+                SELECT 1 FROM user_blocks
+                WHERE
+                    (blocker_id = $1 AND blocked_id = peer.user_id) OR
+                    (blocker_id = peer.user_id AND blocked_id = $1)
+            );
+    "#)
+        .bind(user_id)
+        .fetch_all(pool)
+        .await;
+    match result {
+        Ok(peer_ids) => return Ok(peer_ids),
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    }
+}
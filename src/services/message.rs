@@ -1,45 +1,775 @@
+use chrono::Duration;
 use sqlx::{Pool, Postgres};
 use tracing::error;
 
-use crate::{error::AppError, modules::{message::Message, user::User}};
+use crate::{
+    error::AppError,
+    modules::{
+        message::{
+            CursorDto,
+            EditDto,
+            Message,
+            MessageList,
+            ReactionChange,
+            ReadCursor,
+            SearchQuery,
+            SearchResult,
+            SendDto
+        },
+        user::{User, DELETED_USER_NAME}
+    },
+    services,
+    storage::BlobStore
+};
+
+// the columns of `Message`, binds the viewer id to $1 and `DELETED_USER_NAME` to $2,
+// the messages hidden by the viewer are filtered out, add more filters with AND.
+const MESSAGE_SELECT: &str = r#"
+    SELECT 
+        messages.id,
+        messages.conversation_id,
+        messages.sender_id,
+        users.username as sender_username,
+        COALESCE(users.name, $2) as sender_name,
+        messages.body,
+        messages.deleted_at IS NOT NULL as deleted,
+        (
+            SELECT json_build_object(
+                'id', quoted.id,
+                'sender_id', quoted.sender_id,
+                'sender_name', COALESCE(quoted_sender.name, $2),
+                'body', LEFT(quoted.body, 100),
+                'deleted', quoted.deleted_at IS NOT NULL
+            )
+            FROM messages quoted
+            LEFT JOIN users quoted_sender ON quoted_sender.id = quoted.sender_id
+            WHERE quoted.id = messages.reply_to_message_id
+        ) as reply_to,
+        COALESCE((
+            SELECT json_agg(reaction ORDER BY reaction.first_at)
+            FROM (
+                SELECT
+                    emoji,
+                    COUNT(*) as count,
+                    bool_or(user_id = $1) as reacted_by_me,
+                    MIN(created_at) as first_at
+                FROM message_reactions
+                WHERE message_id = messages.id
+                GROUP BY emoji
+            ) reaction
+        ), '[]') as reactions,
+        COALESCE((
+            SELECT json_agg(json_build_object(
+                'id', attachments.id,
+                'filename', attachments.filename,
+                'mime_type', attachments.mime_type,
+                'size', attachments.size,
+                'width', attachments.width,
+                'height', attachments.height,
+                'blurhash', attachments.blurhash
+            ) ORDER BY attachments.id)
+            FROM attachments
+            WHERE attachments.message_id = messages.id
+        ), '[]') as attachments,
+        NOT EXISTS (
+            SELECT 1 FROM conversation_members member
+            WHERE
+                member.conversation_id = messages.conversation_id AND
+                member.user_id IS DISTINCT FROM messages.sender_id AND
+                COALESCE(member.last_delivered_message_id, 0) < messages.id
+        ) as delivered,
+        -- the read cursor of another member counts only when both share the read receipts.
+        NOT EXISTS (
+            SELECT 1 FROM conversation_members member
+            WHERE
+                member.conversation_id = messages.conversation_id AND
+                member.user_id IS DISTINCT FROM messages.sender_id AND (
+                    COALESCE(member.last_read_message_id, 0) < messages.id OR (
+                        member.user_id <> $1 AND
+                        EXISTS (
+                            SELECT 1 FROM users hiding
+                            WHERE
+                                hiding.id IN (member.user_id, $1) AND
+                                NOT hiding.read_receipts
+                        )
+                    )
+                )
+        ) as readed,
+        messages.created_at,
+        messages.edited_at
+    FROM messages 
+    LEFT JOIN users ON users.id = messages.sender_id
+    WHERE
+        NOT EXISTS (
+            SELECT 1 FROM hidden_messages
+            WHERE
+                hidden_messages.message_id = messages.id AND
+                hidden_messages.user_id = $1
+        )
+"#;
+
+const DEFAULT_PAGE_SIZE: i64 = 20;
+const MAX_PAGE_SIZE: i64 = 100;
+// put around the matched words by ts_headline, in place of the tags
+// so the words are matched in the body and not in its html escapes.
+const SNIPPET_START: char = '\u{E000}';
+const SNIPPET_STOP: char = '\u{E001}';
+
+#[derive(Clone, Copy)]
+pub enum CursorKind {
+    Delivered,
+    Read,
+}
 
 
 pub async fn get_all(
     user: User,
     conversation_id: i32,
     pool: &Pool<Postgres>
-) -> Result<Vec<Message>, AppError> {
-    let result = sqlx::query_as::<_, Message>(r#"
-        SELECT 
-            id,
-            sender_username,
-            receiver_username,
+) -> Result<MessageList, AppError> {
+    let cursors_result = sqlx::query_as::<_, ReadCursor>(r#"
+        SELECT
             conversation_id,
-            body,
-            delivered,
-            readed,
-            to_char(created_at at time zone 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as created_at
-        FROM messages 
-        WHERE
-            conversation_id = $1 AND (
-                sender_username   = $2 OR
-                receiver_username = $2
+            user_id,
+            last_delivered_message_id,
+            CASE WHEN user_id = $2 OR NOT EXISTS (
+                SELECT 1 FROM users hiding
+                WHERE
+                    hiding.id IN (conversation_members.user_id, $2) AND
+                    NOT hiding.read_receipts
+            ) THEN last_read_message_id END as last_read_message_id
+        FROM conversation_members
+        WHERE conversation_id = $1;
+    "#)
+        .bind(conversation_id)
+        .bind(user.id)
+        .fetch_all(pool)
+        .await;
+    let cursors = match cursors_result {
+        Ok(cursors) => cursors,
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    };
+    if !cursors.iter().any(|cursor| cursor.user_id == user.id) {
+        return Err(AppError::NotFoundData);
+    }
+    let result = sqlx::query_as::<_, Message>(&format!(r#"
+        {MESSAGE_SELECT}
+        AND
+            messages.conversation_id = $3
+        ORDER BY messages.id;
+    "#))
+        .bind(user.id)
+        .bind(DELETED_USER_NAME)
+        .bind(conversation_id)
+        .fetch_all(pool)
+        .await;
+    match result {
+        Ok(messages) => return Ok(MessageList { messages, cursors }),
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    }
+}
+
+// the reactions and the read state depend on the viewer.
+pub async fn find(
+    viewer_id: i32,
+    id: i32,
+    pool: &Pool<Postgres>
+) -> Result<Message, AppError> {
+    let result = sqlx::query_as::<_, Message>(&format!(r#"
+        {MESSAGE_SELECT}
+        AND
+            messages.id = $3 AND
+            EXISTS (
+                SELECT 1 FROM conversation_members member
+                WHERE
+                    member.conversation_id = messages.conversation_id AND
+                    member.user_id = $1
             );
+    "#))
+        .bind(viewer_id)
+        .bind(DELETED_USER_NAME)
+        .bind(id)
+        .fetch_one(pool)
+        .await;
+    match result {
+        Ok(message) => return Ok(message),
+        Err(err) => match err {
+            sqlx::Error::RowNotFound => return Err(AppError::NotFoundData),
+            other => {
+                error!("{:#?}", other);
+                return Err(AppError::InternalServerError);
+            }
+        }
+    }
+}
+
+pub async fn create(
+    user: User,
+    conversation_id: i32,
+    send_dto: SendDto,
+    request_limit: i64,
+    pool: &Pool<Postgres>
+) -> Result<Message, AppError> {
+    if services::block::exists_in_conversation(conversation_id, user.id, pool).await? {
+        return Err(AppError::Forbidden);
+    }
+    if let Some(reply_to_message_id) = send_dto.reply_to_message_id {
+        let reply_result = sqlx::query_scalar::<_, bool>(r#"
+            SELECT EXISTS (
+                SELECT 1 FROM messages
+                WHERE
+                    id = $1 AND
+                    conversation_id = $2
+            );
+        "#)
+            .bind(reply_to_message_id)
+            .bind(conversation_id)
+            .fetch_one(pool)
+            .await;
+        match reply_result {
+            Ok(true) => {}
+            Ok(false) => return Err(AppError::ValidationError(
+                "reply_to_message_id: must be a message of this conversation".to_string()
+            )),
+            Err(err) => {
+                error!("{:#?}", err);
+                return Err(AppError::InternalServerError);
+            }
+        }
+    }
+    if !send_dto.attachment_ids.is_empty() {
+        let attachments_result = sqlx::query_scalar::<_, i64>(r#"
+            SELECT COUNT(*) FROM attachments
+            WHERE
+                id = ANY($1) AND
+                conversation_id = $2 AND
+                uploader_id = $3 AND
+                message_id IS NULL;
+        "#)
+            .bind(&send_dto.attachment_ids)
+            .bind(conversation_id)
+            .bind(user.id)
+            .fetch_one(pool)
+            .await;
+        let mut attachment_ids = send_dto.attachment_ids.clone();
+        attachment_ids.sort();
+        attachment_ids.dedup();
+        match attachments_result {
+            Ok(count) => {
+                if count != attachment_ids.len() as i64 {
+                    return Err(AppError::ValidationError(
+                        "attachment_ids: must be your pending uploads to this conversation".to_string()
+                    ));
+                }
+            }
+            Err(err) => {
+                error!("{:#?}", err);
+                return Err(AppError::InternalServerError);
+            }
+        }
+    }
+    // the sends to a conversation are serialized on its row,
+    // so the messages of a request are counted after the others are inserted.
+    let result = async {
+        let mut tx = pool.begin().await?;
+        sqlx::query(r#"
+            SELECT id FROM conversations
+            WHERE id = $1
+            FOR NO KEY UPDATE;
+        "#)
+            .bind(conversation_id)
+            .execute(&mut *tx)
+            .await?;
+        // until a message request is accepted only its initiator can send,
+        // and no more than `request_limit` messages.
+        // the sender has delivered and read its own message.
+        let inserted = sqlx::query_as::<_, (bool, Option<i32>)>(r#"
+            WITH allowed AS (
+                SELECT
+                    NOT pending OR (
+                        user1_id = $2 AND
+                        (
+                            SELECT COUNT(*) FROM messages
+                            WHERE
+                                messages.conversation_id = conversations.id AND
+                                messages.sender_id = $2
+                        ) < $6
+                    ) as allowed
+                FROM conversations
+                WHERE
+                    id = $1 AND
+                    EXISTS (
+                        SELECT 1 FROM conversation_members
+                        WHERE
+                            conversation_id = $1 AND
+                            user_id = $2
+                    )
+            ), inserted AS (
+                INSERT INTO messages (conversation_id, sender_id, body, reply_to_message_id)
+                SELECT $1, $2, $3, $4
+                FROM allowed
+                WHERE allowed.allowed
+                RETURNING id, conversation_id, body, created_at
+            ), conversation AS (
+                UPDATE conversations
+                SET
+                    last_message = inserted.body,
+                    updated_at   = inserted.created_at
+                FROM inserted
+                WHERE conversations.id = inserted.conversation_id
+            ), cursor AS (
+                UPDATE conversation_members
+                SET
+                    last_delivered_message_id = inserted.id,
+                    last_read_message_id      = inserted.id
+                FROM inserted
+                WHERE
+                    conversation_members.conversation_id = inserted.conversation_id AND
+                    conversation_members.user_id = $2
+            ), attached AS (
+                UPDATE attachments
+                SET message_id = inserted.id
+                FROM inserted
+                WHERE
+                    attachments.id = ANY($5) AND
+                    attachments.conversation_id = $1 AND
+                    attachments.uploader_id = $2 AND
+                    attachments.message_id IS NULL
+            )
+            SELECT
+                allowed.allowed,
+                inserted.id
+            FROM allowed
+            LEFT JOIN inserted ON TRUE;
+        "#)
+            .bind(conversation_id)
+            .bind(user.id)
+            .bind(&send_dto.body)
+            .bind(send_dto.reply_to_message_id)
+            .bind(&send_dto.attachment_ids)
+            .bind(request_limit)
+            .fetch_optional(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        return Ok::<_, sqlx::Error>(inserted);
+    }.await;
+    match result {
+        Ok(Some((_, Some(id)))) => return find(user.id, id, pool).await,
+        Ok(Some((_, None))) => return Err(AppError::Forbidden),
+        Ok(None) => return Err(AppError::NotFoundData),
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    }
+}
+
+// only the sender can edit the message and only within the edit window,
+// the replaced body is kept in `message_revisions`.
+pub async fn update(
+    user: User,
+    id: i32,
+    edit_dto: EditDto,
+    edit_window: Duration,
+    pool: &Pool<Postgres>
+) -> Result<Message, AppError> {
+    let message = match find(user.id, id, pool).await {
+        Ok(message) => message,
+        Err(err) => return Err(err)
+    };
+    if message.sender_id != Some(user.id) || message.deleted {
+        return Err(AppError::Forbidden);
+    }
+    // the window is checked on the locked row, an unchanged body is not a new revision.
+    let result = sqlx::query_scalar::<_, i32>(r#"
+        WITH target AS (
+            SELECT id, body FROM messages
+            WHERE
+                id = $1 AND
+                sender_id = $2 AND
+                deleted_at IS NULL AND
+                created_at > CURRENT_TIMESTAMP - $4
+            FOR UPDATE
+        ), revision AS (
+            INSERT INTO message_revisions (message_id, body)
+            SELECT id, body FROM target
+            WHERE body <> $3
+        ), updated AS (
+            UPDATE messages
+            SET
+                body      = $3,
+                edited_at = CURRENT_TIMESTAMP
+            FROM target
+            WHERE
+                messages.id = target.id AND
+                target.body <> $3
+            RETURNING messages.id, messages.conversation_id, messages.body
+        ), conversation AS (
+            UPDATE conversations
+            SET last_message = updated.body
+            FROM updated
+            WHERE
+                conversations.id = updated.conversation_id AND
+                updated.id = (
+                    SELECT MAX(id) FROM messages
+                    WHERE conversation_id = updated.conversation_id
+                )
+        )
+        SELECT id FROM target;
+    "#)
+        .bind(id)
+        .bind(user.id)
+        .bind(&edit_dto.body)
+        .bind(edit_window)
+        .fetch_optional(pool)
+        .await;
+    match result {
+        Ok(Some(_)) => return find(user.id, id, pool).await,
+        // the edit window is over or the message was deleted meanwhile.
+        Ok(None) => return Err(AppError::Forbidden),
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    }
+}
+
+// hides the message only for this user, any member can do it.
+pub async fn hide(
+    user: User,
+    id: i32,
+    pool: &Pool<Postgres>
+) -> Result<Message, AppError> {
+    let message = match find(user.id, id, pool).await {
+        Ok(message) => message,
+        Err(err) => return Err(err)
+    };
+    let result = sqlx::query(r#"
+        INSERT INTO hidden_messages (message_id, user_id)
+        VALUES ($1, $2)
+        ON CONFLICT DO NOTHING;
+    "#)
+        .bind(id)
+        .bind(user.id)
+        .execute(pool)
+        .await;
+    match result {
+        Ok(_) => return Ok(message),
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    }
+}
+
+// only the sender can delete the message for everyone and only within the delete window,
+// the body, the revisions and the attachments are wiped and the message is kept as a tombstone.
+pub async fn delete(
+    user: User,
+    id: i32,
+    delete_window: Duration,
+    store: &dyn BlobStore,
+    pool: &Pool<Postgres>
+) -> Result<Message, AppError> {
+    let message = match find(user.id, id, pool).await {
+        Ok(message) => message,
+        Err(err) => return Err(err)
+    };
+    if message.sender_id != Some(user.id) {
+        return Err(AppError::Forbidden);
+    }
+    if message.deleted {
+        return Ok(message);
+    }
+    // the window is checked by the UPDATE, the rest only follows the deleted row.
+    let result = sqlx::query_as::<_, (i32, Option<String>)>(r#"
+        WITH deleted AS (
+            UPDATE messages
+            SET
+                body       = '',
+                deleted_at = CURRENT_TIMESTAMP
+            WHERE
+                id = $1 AND
+                sender_id = $2 AND
+                deleted_at IS NULL AND
+                created_at > CURRENT_TIMESTAMP - $3
+            RETURNING id, conversation_id
+        ), revisions AS (
+            DELETE FROM message_revisions
+            USING deleted
+            WHERE message_revisions.message_id = deleted.id
+        ), reactions AS (
+            DELETE FROM message_reactions
+            USING deleted
+            WHERE message_reactions.message_id = deleted.id
+        ), conversation AS (
+            UPDATE conversations
+            SET last_message = NULL
+            FROM deleted
+            WHERE
+                conversations.id = deleted.conversation_id AND
+                deleted.id = (
+                    SELECT MAX(id) FROM messages
+                    WHERE conversation_id = deleted.conversation_id
+                )
+        ), removed AS (
+            DELETE FROM attachments
+            USING deleted
+            WHERE attachments.message_id = deleted.id
+            RETURNING attachments.storage_key, attachments.thumbnail_key
+        )
+        SELECT
+            deleted.id,
+            blobs.storage_key
+        FROM deleted
+        LEFT JOIN (
+            SELECT storage_key FROM removed
+            UNION ALL
+            SELECT thumbnail_key FROM removed
+            WHERE thumbnail_key IS NOT NULL
+        ) blobs ON TRUE;
+    "#)
+        .bind(id)
+        .bind(user.id)
+        .bind(delete_window)
+        .fetch_all(pool)
+        .await;
+    match result {
+        Ok(rows) => {
+            // the delete window is over, or another request deleted it meanwhile.
+            if rows.is_empty() {
+                let message = find(user.id, id, pool).await?;
+                if !message.deleted {
+                    return Err(AppError::Forbidden);
+                }
+                return Ok(message);
+            }
+            services::attachment::delete_blobs(
+                rows.into_iter().filter_map(|(_, storage_key)| storage_key).collect(),
+                store
+            ).await;
+            return find(user.id, id, pool).await;
+        }
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    }
+}
+
+// adds or removes the reaction of the user,
+// returns `None` if there was nothing to change.
+pub async fn react(
+    user: User,
+    id: i32,
+    emoji: String,
+    added: bool,
+    pool: &Pool<Postgres>
+) -> Result<Option<ReactionChange>, AppError> {
+    let message = match find(user.id, id, pool).await {
+        Ok(message) => message,
+        Err(err) => return Err(err)
+    };
+    if message.deleted {
+        return Err(AppError::BadRequest);
+    }
+    let query = if added {
+        // the message can be deleted for everyone since it was found.
+        sqlx::query(r#"
+            INSERT INTO message_reactions (message_id, user_id, emoji)
+            SELECT $1, $2, $3
+            WHERE EXISTS (
+                SELECT 1 FROM messages
+                WHERE
+                    id = $1 AND
+                    deleted_at IS NULL
+            )
+            ON CONFLICT DO NOTHING;
+        "#)
+    } else {
+        sqlx::query(r#"
+            DELETE FROM message_reactions
+            WHERE
+                message_id = $1 AND
+                user_id    = $2 AND
+                emoji      = $3;
+        "#)
+    };
+    let result = query
+        .bind(id)
+        .bind(user.id)
+        .bind(&emoji)
+        .execute(pool)
+        .await;
+    match result {
+        Ok(data) => {
+            if data.rows_affected() < 1 {
+                return Ok(None);
+            }
+            return Ok(Some(ReactionChange {
+                message_id: message.id,
+                conversation_id: message.conversation_id,
+                user_id: user.id,
+                emoji,
+                added
+            }));
+        }
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    }
+}
+
+// moves the cursor of the user forward, it never goes back.
+// reading a message also means it was delivered.
+pub async fn update_cursor(
+    user: User,
+    conversation_id: i32,
+    kind: CursorKind,
+    cursor_dto: CursorDto,
+    pool: &Pool<Postgres>
+) -> Result<ReadCursor, AppError> {
+    let read = matches!(kind, CursorKind::Read);
+    let result = sqlx::query_as::<_, ReadCursor>(r#"
+        UPDATE conversation_members
+        SET
+            last_delivered_message_id = GREATEST(last_delivered_message_id, $3),
+            last_read_message_id      = CASE WHEN $4
+                THEN GREATEST(last_read_message_id, $3)
+                ELSE last_read_message_id
+            END
+        WHERE
+            conversation_id = $1 AND
+            user_id = $2 AND
+            EXISTS (
+                SELECT 1 FROM messages
+                WHERE
+                    id = $3 AND
+                    conversation_id = $1
+            )
+        RETURNING
+            conversation_id,
+            user_id,
+            last_delivered_message_id,
+            last_read_message_id;
     "#)
         .bind(conversation_id)
-        .bind(&user.username)
+        .bind(user.id)
+        .bind(cursor_dto.message_id)
+        .bind(read)
+        .fetch_optional(pool)
+        .await;
+    match result {
+        Ok(Some(cursor)) => return Ok(cursor),
+        Ok(None) => return Err(AppError::NotFoundData),
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    }
+}
+
+// searches the messages of the conversations the user is a member of,
+// the best matches first.
+pub async fn search(
+    user: User,
+    search_query: SearchQuery,
+    pool: &Pool<Postgres>
+) -> Result<Vec<SearchResult>, AppError> {
+    let limit = search_query.limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let offset = search_query.offset.unwrap_or(0).max(0);
+    let result = sqlx::query_as::<_, SearchResult>(r#"
+        SELECT
+            messages.id as message_id,
+            messages.conversation_id,
+            messages.sender_id,
+            COALESCE(users.name, $2) as sender_name,
+            -- the markers typed in the body are dropped, only ts_headline puts them.
+            ts_headline(
+                'simple',
+                translate(messages.body, $9, ''),
+                query,
+                $10
+            ) as snippet,
+            ts_rank(messages.body_tsv, query) as rank,
+            messages.created_at
+        FROM messages
+        JOIN conversation_members member ON
+            member.conversation_id = messages.conversation_id AND
+            member.user_id = $1
+        LEFT JOIN users ON users.id = messages.sender_id,
+        websearch_to_tsquery('simple', $3) query
+        WHERE
+            messages.body_tsv @@ query AND
+            messages.deleted_at IS NULL AND
+            NOT EXISTS (
+                SELECT 1 FROM hidden_messages
+                WHERE
+                    hidden_messages.message_id = messages.id AND
+                    hidden_messages.user_id = $1
+            ) AND
+            ($4::INT IS NULL OR messages.conversation_id = $4) AND
+            ($5::TIMESTAMPTZ IS NULL OR messages.created_at >= $5) AND
+            ($6::TIMESTAMPTZ IS NULL OR messages.created_at < $6)
+        ORDER BY
+            rank DESC,
+            messages.id DESC
+        LIMIT $7
+        OFFSET $8;
+    "#)
+        .bind(user.id)
+        .bind(DELETED_USER_NAME)
+        .bind(&search_query.q)
+        .bind(search_query.conversation_id)
+        .bind(search_query.from)
+        .bind(search_query.before)
+        .bind(limit)
+        .bind(offset)
+        .bind(format!("{}{}", SNIPPET_START, SNIPPET_STOP))
+        .bind(format!(
+            "StartSel={}, StopSel={}, MaxFragments=2, MaxWords=20, MinWords=5",
+            SNIPPET_START,
+            SNIPPET_STOP
+        ))
         .fetch_all(pool)
         .await;
     match result {
-        Ok(messages) => {
-            if messages.len() < 1 {
-                return Err(AppError::NotFoundData);
+        Ok(mut results) => {
+            for result in &mut results {
+                result.snippet = highlight(&result.snippet);
             }
-            return Ok(messages);
+            return Ok(results);
         }
         Err(err) => {
             error!("{:#?}", err);
             return Err(AppError::InternalServerError);
         }
     }
-}
\ No newline at end of file
+}
+
+// the snippet is html escaped, then its markers turn into <mark></mark>.
+fn highlight(snippet: &str) -> String {
+    let mut html = String::with_capacity(snippet.len());
+    for character in snippet.chars() {
+        match character {
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            SNIPPET_START => html.push_str("<mark>"),
+            SNIPPET_STOP => html.push_str("</mark>"),
+            other => html.push(other)
+        }
+    }
+    return html;
+}
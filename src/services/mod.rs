@@ -1,4 +1,7 @@
 pub mod user;
 pub mod session;
 pub mod conversation;
-pub mod message;
\ No newline at end of file
+pub mod message;
+pub mod attachment;
+pub mod block;
+pub mod contact;
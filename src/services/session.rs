@@ -29,15 +29,14 @@ pub async fn create(
         Ok(_) => return Ok(session),
         Err(e) => match e {
             sqlx::Error::Database(err) => {
-                if let Some(err_code) = err.code() {
-                    if err_code == "23505" {
-                        error!(
-                            "The session is found, can not create session for '{}' the uuid is '{}'!", 
-                            user_id,
-                            session
-                        );
-                        return Err(AppError::InternalServerError);
-                    }
+                if let Some(err_code) = err.code() &&
+                    err_code == "23505" {
+                    error!(
+                        "The session is found, can not create session for '{}' the uuid is '{}'!", 
+                        user_id,
+                        session
+                    );
+                    return Err(AppError::InternalServerError);
                 }
                 error!("{:#?}", err);
                 return Err(AppError::InternalServerError);
@@ -62,8 +61,21 @@ pub async fn get_user_by_session(
             username, 
             password,
             gender,
-            to_char(create_at at time zone 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as create_at, 
-            to_char(update_at at time zone 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as update_at
+            create_at,
+            update_at,
+            last_seen_at,
+            last_seen_visibility,
+            email_visibility,
+            gender_visibility,
+            conversation_visibility,
+            discoverable,
+            read_receipts,
+            avatar_id,
+            bio,
+            status_text,
+            status_expires_at,
+            time_zone,
+            version
         FROM users 
         WHERE
             users.id = (
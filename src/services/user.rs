@@ -7,20 +7,68 @@ use argon2::{
     PasswordHash, 
     PasswordHasher
 };
-use sqlx::{Pool, Postgres};
+use chrono::{Duration, Utc};
+use sqlx::{Pool, Postgres, QueryBuilder};
 use tracing::error;
+use uuid::Uuid;
 
 use crate::{
     error::AppError, 
-    modules::user::{
-        CreateDto, 
-        LoginDto, 
-        UpdateInfoDto, 
-        UpdatePassDto,
-        User
-    }
+    modules::{
+        contact::ContactStatus,
+        user::{
+            CreateDto, 
+            LoginDto, 
+            PresenceView,
+            PrivacyDto,
+            Profile,
+            SearchQuery,
+            SearchResult,
+            UpdateInfoDto, 
+            UpdatePassDto,
+            User,
+            Visibility
+        }
+    },
+    presence::Presence,
+    services,
+    storage::BlobStore,
+    utils::{media, patch::Patch}
 };
 
+// the condition on `users` for the users the viewer ($2) can look up:
+// no block between them, and discoverable unless they are accepted contacts or peers.
+const VISIBLE_TO_VIEWER: &str = r#"
+    NOT EXISTS (
+        SELECT 1 FROM user_blocks
+        WHERE
+            (blocker_id = users.id AND blocked_id = $2) OR
+            (blocker_id = $2 AND blocked_id = users.id)
+    ) AND (
+        users.id = $2 OR
+        users.discoverable OR
+        EXISTS (
+            SELECT 1 FROM contacts
+            WHERE
+                contacts.accepted_at IS NOT NULL AND (
+                    (requester_id = users.id AND addressee_id = $2) OR
+                    (requester_id = $2 AND addressee_id = users.id)
+                )
+        ) OR
+        EXISTS (
+            SELECT 1
+            FROM conversation_members member
+            JOIN conversation_members peer ON
+                peer.conversation_id = member.conversation_id AND
+                peer.user_id = $2
+            WHERE member.user_id = users.id
+        )
+    )
+"#;
+
+const DEFAULT_PAGE_SIZE: i64 = 20;
+const MAX_PAGE_SIZE: i64 = 100;
+
 
 pub async fn create(
     create_dto: CreateDto,
@@ -29,7 +77,7 @@ pub async fn create(
     let salt = SaltString::generate(&mut OsRng);
     let password = Argon2::default()
         .hash_password(
-            &create_dto.password.as_bytes(), 
+            create_dto.password.as_bytes(), 
             &salt
         ).unwrap();
     let result = sqlx::query_as::<_, User>(r#"
@@ -42,24 +90,36 @@ pub async fn create(
             password,
             email,
             gender,
-            to_char(create_at at time zone 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as create_at, 
-            to_char(update_at at time zone 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as update_at
+            create_at,
+            update_at,
+            last_seen_at,
+            last_seen_visibility,
+            email_visibility,
+            gender_visibility,
+            conversation_visibility,
+            discoverable,
+            read_receipts,
+            avatar_id,
+            bio,
+            status_text,
+            status_expires_at,
+            time_zone,
+            version
     "#)
         .bind(&create_dto.name)
         .bind(&create_dto.username)
         .bind(&create_dto.email)
-        .bind(&password.to_string())
-        .bind(if create_dto.gender.is_some() {create_dto.gender.unwrap()} else {false} )
+        .bind(password.to_string())
+        .bind(create_dto.gender)
         .fetch_one(pool)
         .await;
     match result {
         Ok(data) => return Ok(data),
         Err(e) => match e {
             sqlx::Error::Database(db_err) => {
-                if let Some(err_code) = db_err.code() {
-                    if err_code == "23505" {
-                        return Err(AppError::UserFound);
-                    }
+                if let Some(err_code) = db_err.code() &&
+                    err_code == "23505" {
+                    return Err(AppError::UserFound);
                 }
                 error!("{:#?}", db_err);
                 return Err(AppError::InternalServerError);
@@ -84,8 +144,21 @@ pub async fn login(
             password,
             email,
             gender,
-            to_char(create_at at time zone 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as create_at, 
-            to_char(update_at at time zone 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as update_at
+            create_at,
+            update_at,
+            last_seen_at,
+            last_seen_visibility,
+            email_visibility,
+            gender_visibility,
+            conversation_visibility,
+            discoverable,
+            read_receipts,
+            avatar_id,
+            bio,
+            status_text,
+            status_expires_at,
+            time_zone,
+            version
         FROM users
         WHERE username = $1;
     "#)
@@ -116,11 +189,14 @@ pub async fn login(
     }
 }
 
+// the users that blocked the viewer or that the viewer blocked are not found,
+// neither are the undiscoverable users that are not accepted contacts or peers of the viewer.
 pub async fn find(
     username: String,
+    viewer_id: i32,
     pool: &Pool<Postgres>
 ) -> Result<User, AppError> {
-    let result = sqlx::query_as::<_, User>(r#"
+    let result = sqlx::query_as::<_, User>(&format!(r#"
         SELECT 
             id,
             name,
@@ -128,12 +204,28 @@ pub async fn find(
             password,
             email,
             gender,
-            to_char(create_at at time zone 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as create_at, 
-            to_char(update_at at time zone 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as update_at
+            create_at,
+            update_at,
+            last_seen_at,
+            last_seen_visibility,
+            email_visibility,
+            gender_visibility,
+            conversation_visibility,
+            discoverable,
+            read_receipts,
+            avatar_id,
+            bio,
+            status_text,
+            status_expires_at,
+            time_zone,
+            version
         FROM users
-        WHERE username = $1;
-    "#)
+        WHERE
+            username = $1 AND
+            {VISIBLE_TO_VIEWER};
+    "#))
         .bind(&username)
+        .bind(viewer_id)
         .fetch_one(pool)
         .await;
     match result {
@@ -148,27 +240,64 @@ pub async fn find(
     }
 }
 
+// only the sent fields are written, `version` is the one the client last saw (`If-Match`),
+// the update is refused with `PreconditionFailed` when the profile changed since.
 pub async fn update_information(
     user: User,
     update_info_dto: UpdateInfoDto,
+    version: Option<i32>,
     pool: &Pool<Postgres>
 ) -> Result<User, AppError> {
-    if  update_info_dto.name.is_none()     &&
-        update_info_dto.username.is_none() &&
-        update_info_dto.email.is_none()    &&
-        update_info_dto.gender.is_none() {
+    if  update_info_dto.name.is_none()        &&
+        update_info_dto.username.is_none()    &&
+        update_info_dto.email.is_none()       &&
+        update_info_dto.gender.is_absent()    &&
+        update_info_dto.bio.is_absent()       &&
+        update_info_dto.status_text.is_absent() &&
+        update_info_dto.status_expires_at.is_absent() &&
+        update_info_dto.time_zone.is_absent() {
         return Err(AppError::BadRequest);
     }
-    let result = sqlx::query_as::<_, User>(r#"
+    // a new status without an expiry never expires.
+    let status_expires_at = match update_info_dto.status_text {
+        Patch::Absent => update_info_dto.status_expires_at.into_option(),
+        Patch::Null => Some(None),
+        Patch::Value(_) => Some(update_info_dto.status_expires_at.into_option().flatten())
+    };
+    let mut query = QueryBuilder::<Postgres>::new(r#"
         UPDATE users
         SET
-            name      = $1,
-            username  = $2,
-            email     = $3,
-            gender    = $4,
-            update_at = CURRENT_TIMESTAMP
-        WHERE 
-            id = $5
+            update_at = CURRENT_TIMESTAMP,
+            version   = version + 1"#);
+    if let Some(name) = update_info_dto.name {
+        query.push(", name = ").push_bind(name);
+    }
+    if let Some(username) = update_info_dto.username {
+        query.push(", username = ").push_bind(username);
+    }
+    if let Some(email) = update_info_dto.email {
+        query.push(", email = ").push_bind(email);
+    }
+    if let Some(gender) = update_info_dto.gender.into_option() {
+        query.push(", gender = ").push_bind(gender);
+    }
+    if let Some(bio) = update_info_dto.bio.into_option() {
+        query.push(", bio = ").push_bind(bio);
+    }
+    if let Some(status_text) = update_info_dto.status_text.into_option() {
+        query.push(", status_text = ").push_bind(status_text);
+    }
+    if let Some(status_expires_at) = status_expires_at {
+        query.push(", status_expires_at = ").push_bind(status_expires_at);
+    }
+    if let Some(time_zone) = update_info_dto.time_zone.into_option() {
+        query.push(", time_zone = ").push_bind(time_zone);
+    }
+    query.push(" WHERE id = ").push_bind(user.id);
+    if let Some(version) = version {
+        query.push(" AND version = ").push_bind(version);
+    }
+    query.push(r#"
         RETURNING
             id,
             name,
@@ -176,25 +305,35 @@ pub async fn update_information(
             password,
             email,
             gender,
-            to_char(create_at at time zone 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as create_at, 
-            to_char(update_at at time zone 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as update_at
-    "#)
-        .bind( & if update_info_dto.name.is_some() { update_info_dto.name.unwrap() } else { user.name })
-        .bind( & if update_info_dto.username.is_some() { update_info_dto.username.unwrap() } else { user.username })
-        .bind( & if update_info_dto.email.is_some() { update_info_dto.email.unwrap() } else { user.email })
-        .bind( & if update_info_dto.gender.is_some() { update_info_dto.gender.unwrap() } else { user.gender })
-        .bind( user.id )
+            create_at,
+            update_at,
+            last_seen_at,
+            last_seen_visibility,
+            email_visibility,
+            gender_visibility,
+            conversation_visibility,
+            discoverable,
+            read_receipts,
+            avatar_id,
+            bio,
+            status_text,
+            status_expires_at,
+            time_zone,
+            version
+    "#);
+    let result = query
+        .build_query_as::<User>()
         .fetch_one(pool)
         .await;
     match result {
         Ok(data) => return Ok(data),
         Err(err) => match err {
+            sqlx::Error::RowNotFound => return Err(AppError::PreconditionFailed),
             sqlx::Error::Database(e) => {
-                if let Some(code) = e.code() {
-                    if code == "23505" {
-                        return Err(AppError::UserFound);
-                    }
-                } 
+                if let Some(code) = e.code() &&
+                    code == "23505" {
+                    return Err(AppError::UserFound);
+                }
                 error!("{:#?}", e);
                 return Err(AppError::InternalServerError);
             }
@@ -206,6 +345,404 @@ pub async fn update_information(
     }
 }
 
+// matches the prefix of the username or the name, or their trigrams for the typos.
+pub async fn search(
+    user: &User,
+    search_query: SearchQuery,
+    pool: &Pool<Postgres>
+) -> Result<Vec<SearchResult>, AppError> {
+    let limit = search_query.limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let offset = search_query.offset.unwrap_or(0).max(0);
+    let query = search_query.q.trim().to_lowercase();
+    if query.is_empty() {
+        return Err(AppError::ValidationError("q: min=1, max=100".to_string()));
+    }
+    // the LIKE wildcards typed by the user are matched literally.
+    let prefix = format!(
+        "{}%",
+        query.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+    );
+    let result = sqlx::query_as::<_, SearchResult>(&format!(r#"
+        SELECT
+            users.id,
+            users.username,
+            users.name,
+            CASE WHEN users.gender_visibility = 'everyone' OR (
+                users.gender_visibility = 'contacts' AND
+                EXISTS (
+                    SELECT 1 FROM contacts
+                    WHERE
+                        contacts.accepted_at IS NOT NULL AND (
+                            (requester_id = users.id AND addressee_id = $2) OR
+                            (requester_id = $2 AND addressee_id = users.id)
+                        )
+                )
+            ) THEN users.gender END as gender,
+            EXISTS (
+                SELECT 1 FROM contacts
+                WHERE
+                    contacts.accepted_at IS NOT NULL AND (
+                        (requester_id = users.id AND addressee_id = $2) OR
+                        (requester_id = $2 AND addressee_id = users.id)
+                    )
+            ) as is_contact,
+            GREATEST(
+                similarity(lower(users.username), $1),
+                similarity(lower(users.name), $1)
+            ) as rank
+        FROM users
+        WHERE
+            users.id <> $2 AND (
+                lower(users.username) LIKE $3 OR
+                lower(users.name) LIKE $3 OR
+                lower(users.username) % $1 OR
+                lower(users.name) % $1
+            ) AND
+            {VISIBLE_TO_VIEWER}
+        ORDER BY
+            is_contact DESC,
+            (lower(users.username) LIKE $3 OR lower(users.name) LIKE $3) DESC,
+            rank DESC,
+            users.id
+        LIMIT $4
+        OFFSET $5;
+    "#))
+        .bind(&query)
+        .bind(user.id)
+        .bind(&prefix)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(pool)
+        .await;
+    match result {
+        Ok(results) => return Ok(results),
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    }
+}
+
+// stores the avatar in every size and replaces the previous one.
+pub async fn update_avatar(
+    user: User,
+    data: Vec<u8>,
+    store: &dyn BlobStore,
+    pool: &Pool<Postgres>
+) -> Result<User, AppError> {
+    let avatars = match tokio::task::spawn_blocking(move || media::make_avatars(&data)).await {
+        Ok(Some(avatars)) => avatars,
+        Ok(None) => return Err(AppError::UnsupportedMediaType),
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    };
+    let avatar_id = Uuid::new_v4().to_string();
+    for (size, avatar) in avatars {
+        if let Err(err) = store.put(&avatar_key(&avatar_id, size), avatar).await {
+            services::attachment::delete_blobs(avatar_keys(&avatar_id), store).await;
+            return Err(err);
+        }
+    }
+    let result = set_avatar(&user, Some(&avatar_id), store, pool).await;
+    if result.is_err() {
+        services::attachment::delete_blobs(avatar_keys(&avatar_id), store).await;
+    }
+    return result;
+}
+
+pub async fn delete_avatar(
+    user: User,
+    store: &dyn BlobStore,
+    pool: &Pool<Postgres>
+) -> Result<User, AppError> {
+    if user.avatar_id.is_none() {
+        return Err(AppError::NotFoundData);
+    }
+    return set_avatar(&user, None, store, pool).await;
+}
+
+// the user after the update of the avatar, with the avatar it replaced.
+#[derive(sqlx::FromRow)]
+struct AvatarChange {
+    #[sqlx(flatten)]
+    user: User,
+    previous_avatar_id: Option<String>,
+}
+
+// the files of the previous avatar are deleted once it is replaced,
+// it is read under the row lock so a concurrent change can not leave it behind.
+async fn set_avatar(
+    user: &User,
+    avatar_id: Option<&str>,
+    store: &dyn BlobStore,
+    pool: &Pool<Postgres>
+) -> Result<User, AppError> {
+    let result = sqlx::query_as::<_, AvatarChange>(r#"
+        WITH old AS (
+            SELECT id, avatar_id FROM users
+            WHERE id = $2
+            FOR UPDATE
+        )
+        UPDATE users
+        SET
+            avatar_id = $1,
+            update_at = CURRENT_TIMESTAMP,
+            version   = users.version + 1
+        FROM old
+        WHERE users.id = old.id
+        RETURNING
+            users.id,
+            users.name,
+            users.username,
+            users.password,
+            users.email,
+            users.gender,
+            users.create_at,
+            users.update_at,
+            users.last_seen_at,
+            users.last_seen_visibility,
+            users.email_visibility,
+            users.gender_visibility,
+            users.conversation_visibility,
+            users.discoverable,
+            users.read_receipts,
+            users.avatar_id,
+            users.bio,
+            users.status_text,
+            users.status_expires_at,
+            users.time_zone,
+            users.version,
+            old.avatar_id as previous_avatar_id
+    "#)
+        .bind(avatar_id)
+        .bind(user.id)
+        .fetch_one(pool)
+        .await;
+    match result {
+        Ok(change) => {
+            if let Some(previous_id) = &change.previous_avatar_id {
+                services::attachment::delete_blobs(avatar_keys(previous_id), store).await;
+            }
+            return Ok(change.user);
+        }
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    }
+}
+
+// the avatar id and the file of the user in one of the AVATAR_SIZES.
+pub async fn get_avatar(
+    viewer_id: i32,
+    username: String,
+    size: u32,
+    store: &dyn BlobStore,
+    pool: &Pool<Postgres>
+) -> Result<(String, Vec<u8>), AppError> {
+    if !media::AVATAR_SIZES.contains(&size) {
+        return Err(AppError::ValidationError(format!("size: one of {:?}", media::AVATAR_SIZES)));
+    }
+    let user = find(username, viewer_id, pool).await?;
+    let Some(avatar_id) = user.avatar_id else {
+        return Err(AppError::NotFoundData);
+    };
+    // the sizes larger than the uploaded image are not made, the next smaller one is sent.
+    for stored_size in media::AVATAR_SIZES.iter().filter(|stored_size| **stored_size <= size) {
+        match store.get(&avatar_key(&avatar_id, *stored_size)).await {
+            Ok(data) => return Ok((avatar_id, data)),
+            Err(AppError::NotFoundData) => continue,
+            Err(err) => return Err(err)
+        }
+    }
+    return Err(AppError::NotFoundData);
+}
+
+fn avatar_key(avatar_id: &str, size: u32) -> String {
+    return format!("{}-{}", avatar_id, size);
+}
+
+fn avatar_keys(avatar_id: &str) -> Vec<String> {
+    return media::AVATAR_SIZES
+        .iter()
+        .map(|size| avatar_key(avatar_id, *size))
+        .collect();
+}
+
+pub async fn get_profile(
+    viewer_id: i32,
+    username: String,
+    presence: &Presence,
+    pool: &Pool<Postgres>
+) -> Result<Profile, AppError> {
+    let user = find(username, viewer_id, pool).await?;
+    let contact = services::contact::get_status(viewer_id, user.id, pool).await?;
+    let presence = get_presence(viewer_id, &user, presence, pool).await?;
+    let visible = |visibility| match visibility {
+        Visibility::Everyone => true,
+        Visibility::Contacts => contact == ContactStatus::Contact,
+        Visibility::Nobody => false
+    };
+    let email_visible = visible(user.email_visibility);
+    let gender_visible = visible(user.gender_visibility);
+    let status_expired = user.status_expires_at
+        .is_some_and(|status_expires_at| status_expires_at <= Utc::now());
+    return Ok(Profile {
+        id: user.id,
+        name: user.name,
+        username: user.username,
+        gender: if gender_visible { user.gender } else { None },
+        email: if email_visible { Some(user.email) } else { None },
+        avatar_id: user.avatar_id,
+        bio: user.bio,
+        status_text: if status_expired { None } else { user.status_text },
+        status_expires_at: if status_expired { None } else { user.status_expires_at },
+        time_zone: user.time_zone,
+        presence,
+        contact
+    });
+}
+
+// what `viewer_id` can see of the presence of the user.
+pub async fn get_presence(
+    viewer_id: i32,
+    user: &User,
+    presence: &Presence,
+    pool: &Pool<Postgres>
+) -> Result<PresenceView, AppError> {
+    if services::block::exists_between(viewer_id, user.id, pool).await? {
+        return Ok(PresenceView { online: false, last_seen_at: None });
+    }
+    let visible = match user.last_seen_visibility {
+        Visibility::Everyone => true,
+        Visibility::Nobody => false,
+        Visibility::Contacts => services::contact::are_contacts(
+            viewer_id,
+            user.id,
+            pool
+        ).await?
+    };
+    if !visible {
+        return Ok(PresenceView { online: false, last_seen_at: None });
+    }
+    return Ok(PresenceView {
+        online: presence.is_online(user.id),
+        last_seen_at: user.last_seen_at
+    });
+}
+
+pub async fn update_privacy(
+    user: User,
+    privacy_dto: PrivacyDto,
+    pool: &Pool<Postgres>
+) -> Result<User, AppError> {
+    let result = sqlx::query_as::<_, User>(r#"
+        UPDATE users
+        SET
+            last_seen_visibility    = COALESCE($1, last_seen_visibility),
+            email_visibility        = COALESCE($2, email_visibility),
+            conversation_visibility = COALESCE($3, conversation_visibility),
+            discoverable            = COALESCE($4, discoverable),
+            read_receipts           = COALESCE($5, read_receipts),
+            gender_visibility       = COALESCE($6, gender_visibility)
+        WHERE id = $7
+        RETURNING
+            id,
+            name,
+            username,
+            password,
+            email,
+            gender,
+            create_at,
+            update_at,
+            last_seen_at,
+            last_seen_visibility,
+            email_visibility,
+            gender_visibility,
+            conversation_visibility,
+            discoverable,
+            read_receipts,
+            avatar_id,
+            bio,
+            status_text,
+            status_expires_at,
+            time_zone,
+            version
+    "#)
+        .bind(privacy_dto.last_seen_visibility)
+        .bind(privacy_dto.email_visibility)
+        .bind(privacy_dto.conversation_visibility)
+        .bind(privacy_dto.discoverable)
+        .bind(privacy_dto.read_receipts)
+        .bind(privacy_dto.gender_visibility)
+        .bind(user.id)
+        .fetch_one(pool)
+        .await;
+    match result {
+        Ok(user) => return Ok(user),
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    }
+}
+
+// keeps the users that share their read receipts.
+pub async fn filter_read_receipts(
+    user_ids: Vec<i32>,
+    pool: &Pool<Postgres>
+) -> Result<Vec<i32>, AppError> {
+    let result = sqlx::query_scalar::<_, i32>(r#"
+        SELECT id FROM users
+        WHERE
+            id = ANY($1) AND
+            read_receipts;
+    "#)
+        .bind(&user_ids)
+        .fetch_all(pool)
+        .await;
+    match result {
+        Ok(user_ids) => return Ok(user_ids),
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    }
+}
+
+// `last_seen_at` is written at most once a minute for the requests of a user.
+pub async fn record_activity(user: &User, pool: &Pool<Postgres>) {
+    if user.last_seen_at.is_some_and(|last_seen_at| Utc::now() - last_seen_at < Duration::minutes(1)) {
+        return;
+    }
+    touch(user.id, pool).await;
+}
+
+// returns who can see the presence now, the setting may have changed since the login.
+// a failure only leaves `last_seen_at` behind and returns `None`.
+pub async fn touch(user_id: i32, pool: &Pool<Postgres>) -> Option<Visibility> {
+    let result = sqlx::query_scalar::<_, Visibility>(r#"
+        UPDATE users
+        SET last_seen_at = CURRENT_TIMESTAMP
+        WHERE id = $1
+        RETURNING last_seen_visibility;
+    "#)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await;
+    match result {
+        Ok(visibility) => return visibility,
+        Err(err) => {
+            error!("{:#?}", err);
+            return None;
+        }
+    }
+}
+
 pub async fn update_password(
     user: User,
     update_pass_dto: UpdatePassDto,
@@ -214,7 +751,7 @@ pub async fn update_password(
     let salt = SaltString::generate(&mut OsRng);
     let password = Argon2::default()
         .hash_password(
-            &update_pass_dto.password.as_bytes(), 
+            update_pass_dto.password.as_bytes(), 
             &salt
         ).unwrap();
     let result = sqlx::query(r#"
@@ -225,7 +762,7 @@ pub async fn update_password(
         WHERE 
             id = $2;
     "#)
-        .bind(&password.to_string())
+        .bind(password.to_string())
         .bind(user.id)
         .execute(pool)
         .await;
@@ -243,24 +780,90 @@ pub async fn update_password(
 
 pub async fn delete(
     user: User,
+    store: &dyn BlobStore,
     pool: &Pool<Postgres>
 ) -> Result<(), AppError> {
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    };
+    // the conversations with an already deleted user have nobody left in them,
+    // the others are kept for the other user and show this user as deleted.
+    let delete_conversations_result = sqlx::query_scalar::<_, String>(r#"
+        WITH deleted AS (
+            DELETE FROM conversations
+            WHERE
+                (user1_id = $1 AND user2_id IS NULL) OR
+                (user2_id = $1 AND user1_id IS NULL)
+            RETURNING id
+        )
+        SELECT blobs.storage_key
+        FROM attachments
+        JOIN deleted ON deleted.id = attachments.conversation_id
+        CROSS JOIN LATERAL unnest(
+            ARRAY[attachments.storage_key, attachments.thumbnail_key]
+        ) AS blobs(storage_key)
+        WHERE blobs.storage_key IS NOT NULL;
+    "#)
+        .bind(user.id)
+        .fetch_all(&mut *tx)
+        .await;
+    let mut storage_keys = match delete_conversations_result {
+        Ok(storage_keys) => storage_keys,
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    };
+    if let Some(avatar_id) = &user.avatar_id {
+        storage_keys.extend(avatar_keys(avatar_id));
+    }
+    // the unfinished uploads go with the user by the cascade, not their files.
+    // locked so no chunk is added to them in between.
+    let uploads_result = sqlx::query_scalar::<_, String>(r#"
+        SELECT blobs.storage_key
+        FROM (
+            SELECT storage_key, part_keys FROM uploads
+            WHERE uploader_id = $1
+            FOR UPDATE
+        ) upload
+        CROSS JOIN LATERAL unnest(
+            array_prepend(upload.storage_key, upload.part_keys)
+        ) AS blobs(storage_key);
+    "#)
+        .bind(user.id)
+        .fetch_all(&mut *tx)
+        .await;
+    match uploads_result {
+        Ok(upload_keys) => storage_keys.extend(upload_keys),
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
+        }
+    }
     let result = sqlx::query(r#"
         DELETE FROM users
         WHERE
             id = $1;
     "#)
         .bind(user.id)
-        .execute(pool)
+        .execute(&mut *tx)
         .await;
-    match result {
-        Ok(_) => return Ok(()),
-        Err(err) => match err {
-            sqlx::Error::RowNotFound => return Err(AppError::UserFound),
-            other => {
-                error!("{:#?}", other);
-                return Err(AppError::InternalServerError);
-            }
+    if let Err(err) = result {
+        error!("{:#?}", err);
+        return Err(AppError::InternalServerError);
+    }
+    match tx.commit().await {
+        Ok(_) => {
+            services::attachment::delete_blobs(storage_keys, store).await;
+            return Ok(());
+        }
+        Err(err) => {
+            error!("{:#?}", err);
+            return Err(AppError::InternalServerError);
         }
     }
 }
@@ -0,0 +1,24 @@
+use std::sync::Arc;
+
+use sqlx::{Pool, Postgres};
+
+use crate::{
+    config::Config,
+    events::EventBus,
+    mailer::Mailer,
+    presence::Presence,
+    storage::BlobStore
+};
+
+
+#[derive(Clone)]
+pub struct AppState {
+    pub pool: Pool<Postgres>,
+    pub config: Arc<Config>,
+    pub events: EventBus,
+    pub presence: Presence,
+    // no mail is sent yet, the mailer is here for the handlers needing it.
+    #[allow(dead_code)]
+    pub mailer: Arc<Mailer>,
+    pub storage: Arc<dyn BlobStore>,
+}
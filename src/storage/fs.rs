@@ -0,0 +1,157 @@
+use std::{io::{ErrorKind, SeekFrom}, path::PathBuf};
+
+use async_trait::async_trait;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tracing::error;
+
+use crate::{error::AppError, storage::BlobStore};
+
+
+// keeps the files in a directory on the server disk.
+pub struct FsStore {
+    root: PathBuf,
+}
+
+impl FsStore {
+    pub fn new(root: PathBuf) -> Self {
+        std::fs::create_dir_all(&root)
+            .expect(">>> Can NOT create the storage directory!");
+        return Self { root };
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, AppError> {
+        // the keys are ours, but never let one escape the root.
+        if key.is_empty() || key.contains(['/', '\\']) || key.starts_with('.') {
+            error!("Invalid storage key '{}'!", key);
+            return Err(AppError::InternalServerError);
+        }
+        return Ok(self.root.join(key));
+    }
+}
+
+#[async_trait]
+impl BlobStore for FsStore {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), AppError> {
+        let path = match self.path(key) {
+            Ok(path) => path,
+            Err(err) => return Err(err)
+        };
+        match tokio::fs::write(&path, data).await {
+            Ok(_) => return Ok(()),
+            Err(err) => {
+                error!("{:#?}", err);
+                return Err(AppError::InternalServerError);
+            }
+        }
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, AppError> {
+        let path = match self.path(key) {
+            Ok(path) => path,
+            Err(err) => return Err(err)
+        };
+        match tokio::fs::read(&path).await {
+            Ok(data) => return Ok(data),
+            Err(err) => {
+                if err.kind() == ErrorKind::NotFound {
+                    return Err(AppError::NotFoundData);
+                }
+                error!("{:#?}", err);
+                return Err(AppError::InternalServerError);
+            }
+        }
+    }
+
+    // the parts are copied into the file a buffer at a time.
+    async fn compose(&self, key: &str, part_keys: &[String]) -> Result<(), AppError> {
+        let path = match self.path(key) {
+            Ok(path) => path,
+            Err(err) => return Err(err)
+        };
+        let mut part_paths = Vec::with_capacity(part_keys.len());
+        for part_key in part_keys {
+            match self.path(part_key) {
+                Ok(part_path) => part_paths.push(part_path),
+                Err(err) => return Err(err)
+            }
+        }
+        let result = async {
+            let mut file = tokio::fs::File::create(&path).await?;
+            for part_path in &part_paths {
+                let mut part = tokio::fs::File::open(part_path).await?;
+                tokio::io::copy(&mut part, &mut file).await?;
+            }
+            file.flush().await?;
+            return Ok::<_, std::io::Error>(());
+        }.await;
+        match result {
+            Ok(_) => return Ok(()),
+            Err(err) => {
+                if err.kind() == ErrorKind::NotFound {
+                    return Err(AppError::NotFoundData);
+                }
+                error!("{:#?}", err);
+                return Err(AppError::InternalServerError);
+            }
+        }
+    }
+
+    // only the range is read, seeking in a long video does not load all of it.
+    async fn get_range(&self, key: &str, start: u64, length: u64) -> Result<Vec<u8>, AppError> {
+        let path = match self.path(key) {
+            Ok(path) => path,
+            Err(err) => return Err(err)
+        };
+        let result = async {
+            let mut file = tokio::fs::File::open(&path).await?;
+            file.seek(SeekFrom::Start(start)).await?;
+            let mut data = Vec::new();
+            file.take(length).read_to_end(&mut data).await?;
+            return Ok::<_, std::io::Error>(data);
+        }.await;
+        match result {
+            Ok(data) => return Ok(data),
+            Err(err) => {
+                if err.kind() == ErrorKind::NotFound {
+                    return Err(AppError::NotFoundData);
+                }
+                error!("{:#?}", err);
+                return Err(AppError::InternalServerError);
+            }
+        }
+    }
+
+    async fn size(&self, key: &str) -> Result<u64, AppError> {
+        let path = match self.path(key) {
+            Ok(path) => path,
+            Err(err) => return Err(err)
+        };
+        match tokio::fs::metadata(&path).await {
+            Ok(metadata) => return Ok(metadata.len()),
+            Err(err) => {
+                if err.kind() == ErrorKind::NotFound {
+                    return Err(AppError::NotFoundData);
+                }
+                error!("{:#?}", err);
+                return Err(AppError::InternalServerError);
+            }
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), AppError> {
+        let path = match self.path(key) {
+            Ok(path) => path,
+            Err(err) => return Err(err)
+        };
+        match tokio::fs::remove_file(&path).await {
+            Ok(_) => return Ok(()),
+            Err(err) => {
+                if err.kind() == ErrorKind::NotFound {
+                    return Ok(());
+                }
+                error!("{:#?}", err);
+                return Err(AppError::InternalServerError);
+            }
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+
+use crate::{config::{Config, StorageBackend}, error::AppError};
+
+pub mod fs;
+pub mod s3;
+
+
+// where the attachment files are kept, the key is generated by us
+// and stored in the `attachments` table.
+#[async_trait]
+pub trait BlobStore: Send + Sync {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), AppError>;
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, AppError>;
+
+    async fn delete(&self, key: &str) -> Result<(), AppError>;
+
+    // writes the files of `part_keys` one after the other to `key`,
+    // without holding the whole file in memory, the parts are kept.
+    async fn compose(&self, key: &str, part_keys: &[String]) -> Result<(), AppError>;
+
+    // the size of the file, without reading it.
+    async fn size(&self, key: &str) -> Result<u64, AppError>;
+
+    // `length` bytes from `start`, the caller keeps the range inside the file.
+    async fn get_range(&self, key: &str, start: u64, length: u64) -> Result<Vec<u8>, AppError> {
+        let data = self.get(key).await?;
+        let start = (start as usize).min(data.len());
+        let end = start.saturating_add(length as usize).min(data.len());
+        return Ok(data[start..end].to_vec());
+    }
+
+    // a url the client can PUT exactly `size` bytes to without going through us,
+    // `None` if the store can not do it.
+    fn presign_put(&self, _key: &str, _size: u64, _expires_in: Duration) -> Option<String> {
+        return None;
+    }
+
+    // a url the client can download the file from without going through us,
+    // `None` if the store can not do it.
+    fn presign_get(
+        &self,
+        _key: &str,
+        _content_type: &str,
+        _content_disposition: &str,
+        _expires_in: Duration
+    ) -> Option<String> {
+        return None;
+    }
+}
+
+pub fn from_config(config: &Config) -> Arc<dyn BlobStore> {
+    match &config.storage {
+        StorageBackend::Fs { dir } => return Arc::new(fs::FsStore::new(dir.into())),
+        StorageBackend::S3 {
+            endpoint,
+            bucket,
+            region,
+            access_key,
+            secret_key
+        } => return Arc::new(s3::S3Store::new(
+            endpoint,
+            bucket,
+            region,
+            access_key,
+            secret_key
+        ))
+    }
+}
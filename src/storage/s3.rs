@@ -0,0 +1,370 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use reqwest::{header, StatusCode, Url};
+use rusty_s3::{actions::CreateMultipartUpload, Bucket, Credentials, S3Action, UrlStyle};
+use tracing::error;
+
+use crate::{error::AppError, storage::BlobStore};
+
+// the requests between us and the object storage.
+const REQUEST_EXPIRES_IN: Duration = Duration::from_secs(60);
+// S3 wants at least 5 MiB in every part of a multipart upload but the last.
+const MULTIPART_PART_LENGTH: usize = 8 * 1024 * 1024;
+
+
+// keeps the files in an S3 compatible object storage (AWS S3, MinIO, ...),
+// the clients can upload and download directly with presigned urls.
+pub struct S3Store {
+    bucket: Bucket,
+    credentials: Credentials,
+    client: reqwest::Client,
+}
+
+impl S3Store {
+    pub fn new(
+        endpoint: &str,
+        bucket: &str,
+        region: &str,
+        access_key: &str,
+        secret_key: &str
+    ) -> Self {
+        let endpoint = Url::parse(endpoint)
+            .expect(">>> CHAT_S3_ENDPOINT is NOT a valid url!");
+        // path style works with every S3 compatible server.
+        let bucket = Bucket::new(
+            endpoint,
+            UrlStyle::Path,
+            bucket.to_string(),
+            region.to_string()
+        )
+            .expect(">>> Can NOT create the S3 bucket!");
+        return Self {
+            bucket,
+            credentials: Credentials::new(access_key, secret_key),
+            client: reqwest::Client::new(),
+        };
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response, AppError> {
+        match request.send().await {
+            Ok(response) => {
+                if response.status().is_success() ||
+                    response.status() == StatusCode::NOT_FOUND {
+                    return Ok(response);
+                }
+                error!(
+                    "S3 responded with {}: {}",
+                    response.status(),
+                    response.text().await.unwrap_or_default()
+                );
+                return Err(AppError::InternalServerError);
+            }
+            Err(err) => {
+                error!("{:#?}", err);
+                return Err(AppError::InternalServerError);
+            }
+        }
+    }
+
+    async fn create_multipart_upload(&self, key: &str) -> Result<String, AppError> {
+        let url = self.bucket
+            .create_multipart_upload(Some(&self.credentials), key)
+            .sign(REQUEST_EXPIRES_IN);
+        let response = self.send(self.client.post(url)).await?;
+        let body = match response.text().await {
+            Ok(body) => body,
+            Err(err) => {
+                error!("{:#?}", err);
+                return Err(AppError::InternalServerError);
+            }
+        };
+        match CreateMultipartUpload::parse_response(&body) {
+            Ok(multipart) => return Ok(multipart.upload_id().to_string()),
+            Err(err) => {
+                error!("{:#?}", err);
+                return Err(AppError::InternalServerError);
+            }
+        }
+    }
+
+    // returns the ETag of the part, the parts are numbered from 1.
+    async fn upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: u16,
+        data: Vec<u8>
+    ) -> Result<String, AppError> {
+        let url = self.bucket
+            .upload_part(Some(&self.credentials), key, part_number, upload_id)
+            .sign(REQUEST_EXPIRES_IN);
+        let response = self.send(self.client.put(url).body(data)).await?;
+        let etag = response.headers()
+            .get(header::ETAG)
+            .and_then(|value| value.to_str().ok());
+        match etag {
+            Some(etag) => return Ok(etag.to_string()),
+            None => {
+                error!("S3 sent no ETag for the part {} of '{}'!", part_number, key);
+                return Err(AppError::InternalServerError);
+            }
+        }
+    }
+
+    async fn complete_multipart_upload(
+        &self,
+        key: &str,
+        upload_id: &str,
+        etags: &[String]
+    ) -> Result<(), AppError> {
+        let action = self.bucket.complete_multipart_upload(
+            Some(&self.credentials),
+            key,
+            upload_id,
+            etags.iter().map(String::as_str)
+        );
+        let url = action.sign(REQUEST_EXPIRES_IN);
+        self.send(self.client.post(url).body(action.body())).await?;
+        return Ok(());
+    }
+
+    // the parts already sent are dropped by the storage.
+    async fn abort_multipart_upload(&self, key: &str, upload_id: &str) {
+        let url = self.bucket
+            .abort_multipart_upload(Some(&self.credentials), key, upload_id)
+            .sign(REQUEST_EXPIRES_IN);
+        if self.send(self.client.delete(url)).await.is_err() {
+            error!("Can not abort the multipart upload of '{}'!", key);
+        }
+    }
+}
+
+#[async_trait]
+impl BlobStore for S3Store {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), AppError> {
+        let url = self.bucket
+            .put_object(Some(&self.credentials), key)
+            .sign(REQUEST_EXPIRES_IN);
+        self.send(self.client.put(url).body(data)).await?;
+        return Ok(());
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, AppError> {
+        let url = self.bucket
+            .get_object(Some(&self.credentials), key)
+            .sign(REQUEST_EXPIRES_IN);
+        let response = self.send(self.client.get(url)).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(AppError::NotFoundData);
+        }
+        match response.bytes().await {
+            Ok(data) => return Ok(data.to_vec()),
+            Err(err) => {
+                error!("{:#?}", err);
+                return Err(AppError::InternalServerError);
+            }
+        }
+    }
+
+    // the parts are downloaded one at a time and sent again as the parts of a multipart upload,
+    // a file that fits in a single part is put at once.
+    async fn compose(&self, key: &str, part_keys: &[String]) -> Result<(), AppError> {
+        let mut upload_id: Option<String> = None;
+        let result = async {
+            let mut buffer = Vec::new();
+            let mut etags = Vec::new();
+            for part_key in part_keys {
+                buffer.extend_from_slice(&self.get(part_key).await?);
+                if buffer.len() < MULTIPART_PART_LENGTH {
+                    continue;
+                }
+                let id = match &upload_id {
+                    Some(id) => id.clone(),
+                    None => {
+                        let id = self.create_multipart_upload(key).await?;
+                        upload_id = Some(id.clone());
+                        id
+                    }
+                };
+                let part_number = etags.len() as u16 + 1;
+                etags.push(self.upload_part(key, &id, part_number, std::mem::take(&mut buffer)).await?);
+            }
+            let Some(id) = &upload_id else {
+                return self.put(key, buffer).await;
+            };
+            if !buffer.is_empty() {
+                let part_number = etags.len() as u16 + 1;
+                etags.push(self.upload_part(key, id, part_number, buffer).await?);
+            }
+            return self.complete_multipart_upload(key, id, &etags).await;
+        }.await;
+        if result.is_err() && let Some(id) = &upload_id {
+            self.abort_multipart_upload(key, id).await;
+        }
+        return result;
+    }
+
+    // only the range is downloaded, seeking in a long video does not load all of it.
+    async fn get_range(&self, key: &str, start: u64, length: u64) -> Result<Vec<u8>, AppError> {
+        if length == 0 {
+            return Ok(Vec::new());
+        }
+        let url = self.bucket
+            .get_object(Some(&self.credentials), key)
+            .sign(REQUEST_EXPIRES_IN);
+        let range = format!("bytes={}-{}", start, start + length - 1);
+        let response = self.send(self.client.get(url).header(header::RANGE, range)).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(AppError::NotFoundData);
+        }
+        // a server that ignores the range sends the whole file.
+        let skip = if response.status() == StatusCode::PARTIAL_CONTENT { 0 } else { start as usize };
+        match response.bytes().await {
+            Ok(data) => {
+                let start = skip.min(data.len());
+                let end = start.saturating_add(length as usize).min(data.len());
+                return Ok(data[start..end].to_vec());
+            }
+            Err(err) => {
+                error!("{:#?}", err);
+                return Err(AppError::InternalServerError);
+            }
+        }
+    }
+
+    async fn size(&self, key: &str) -> Result<u64, AppError> {
+        let url = self.bucket
+            .head_object(Some(&self.credentials), key)
+            .sign(REQUEST_EXPIRES_IN);
+        let response = self.send(self.client.head(url)).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(AppError::NotFoundData);
+        }
+        let size = response.headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok());
+        match size {
+            Some(size) => return Ok(size),
+            None => {
+                error!("S3 sent no Content-Length for '{}'!", key);
+                return Err(AppError::InternalServerError);
+            }
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), AppError> {
+        let url = self.bucket
+            .delete_object(Some(&self.credentials), key)
+            .sign(REQUEST_EXPIRES_IN);
+        self.send(self.client.delete(url)).await?;
+        return Ok(());
+    }
+
+    // the Content-Length is signed, the storage rejects a file of another size.
+    fn presign_put(&self, key: &str, size: u64, expires_in: Duration) -> Option<String> {
+        let mut action = self.bucket.put_object(Some(&self.credentials), key);
+        action.headers_mut().insert("content-length", size.to_string());
+        return Some(action.sign(expires_in).to_string());
+    }
+
+    fn presign_get(
+        &self,
+        key: &str,
+        content_type: &str,
+        content_disposition: &str,
+        expires_in: Duration
+    ) -> Option<String> {
+        let mut action = self.bucket.get_object(Some(&self.credentials), key);
+        action.query_mut().insert("response-content-type", content_type.to_string());
+        action.query_mut().insert("response-content-disposition", content_disposition.to_string());
+        return Some(action.sign(expires_in).to_string());
+    }
+}
+
+// run against an S3 compatible server, e.g. a local MinIO:
+// CHAT_TEST_S3_ENDPOINT=http://127.0.0.1:9000 cargo test -- --ignored
+// the bucket is created if needed.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_store() -> S3Store {
+        let endpoint = std::env::var("CHAT_TEST_S3_ENDPOINT")
+            .expect("CHAT_TEST_S3_ENDPOINT is required by the S3 tests");
+        let env = |name: &str, default: &str| std::env::var(name).unwrap_or(default.to_string());
+        return S3Store::new(
+            &endpoint,
+            &env("CHAT_TEST_S3_BUCKET", "chat-test"),
+            &env("CHAT_TEST_S3_REGION", "us-east-1"),
+            &env("CHAT_TEST_S3_ACCESS_KEY", "minioadmin"),
+            &env("CHAT_TEST_S3_SECRET_KEY", "minioadmin")
+        );
+    }
+
+    async fn create_bucket(store: &S3Store) {
+        let url = store.bucket
+            .create_bucket(&store.credentials)
+            .sign(REQUEST_EXPIRES_IN);
+        // an existing bucket is a conflict, that is fine.
+        let _ = store.client.put(url).header(header::CONTENT_LENGTH, 0).send().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs CHAT_TEST_S3_ENDPOINT"]
+    async fn put_get_and_delete() {
+        let store = test_store();
+        create_bucket(&store).await;
+        let key = uuid::Uuid::new_v4().to_string();
+        store.put(&key, b"0123456789".to_vec()).await.unwrap();
+        assert_eq!(store.size(&key).await.unwrap(), 10);
+        assert_eq!(store.get(&key).await.unwrap(), b"0123456789");
+        assert_eq!(store.get_range(&key, 2, 3).await.unwrap(), b"234");
+        assert_eq!(store.get_range(&key, 8, 100).await.unwrap(), b"89");
+        store.delete(&key).await.unwrap();
+        assert_eq!(store.size(&key).await, Err(AppError::NotFoundData));
+        assert_eq!(store.get(&key).await, Err(AppError::NotFoundData));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs CHAT_TEST_S3_ENDPOINT"]
+    async fn compose_parts() {
+        let store = test_store();
+        create_bucket(&store).await;
+        // three parts of 4 MiB, sent as a part of 8 MiB and a last one of 4 MiB.
+        let mut part_keys = Vec::new();
+        let mut expected = Vec::new();
+        for byte in [b'a', b'b', b'c'] {
+            let part_key = uuid::Uuid::new_v4().to_string();
+            let part = vec![byte; 4 * 1024 * 1024];
+            store.put(&part_key, part.clone()).await.unwrap();
+            expected.extend_from_slice(&part);
+            part_keys.push(part_key);
+        }
+        let key = uuid::Uuid::new_v4().to_string();
+        store.compose(&key, &part_keys).await.unwrap();
+        assert_eq!(store.get(&key).await.unwrap(), expected);
+        let small_key = uuid::Uuid::new_v4().to_string();
+        store.compose(&small_key, &part_keys[..1]).await.unwrap();
+        assert_eq!(store.size(&small_key).await.unwrap(), 4 * 1024 * 1024);
+        for part_key in part_keys.iter().chain([&key, &small_key]) {
+            store.delete(part_key).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs CHAT_TEST_S3_ENDPOINT"]
+    async fn presigned_put() {
+        let store = test_store();
+        create_bucket(&store).await;
+        let key = uuid::Uuid::new_v4().to_string();
+        let url = store.presign_put(&key, 5, Duration::from_secs(60)).unwrap();
+        assert!(url.contains("X-Amz-SignedHeaders=content-length%3Bhost"));
+        let response = store.client.put(&url).body(b"hello".to_vec()).send().await.unwrap();
+        assert!(response.status().is_success());
+        assert_eq!(store.size(&key).await.unwrap(), 5);
+        assert_eq!(store.get(&key).await.unwrap(), b"hello");
+        store.delete(&key).await.unwrap();
+    }
+}
@@ -0,0 +1,323 @@
+use std::io::Cursor;
+
+use bytes::Bytes;
+use image::{
+    codecs::jpeg::JpegEncoder,
+    imageops::FilterType,
+    DynamicImage,
+    ImageDecoder,
+    ImageReader,
+    Limits,
+    RgbImage
+};
+use img_parts::{DynImage, ImageEXIF};
+
+// the thumbnails fit in a THUMBNAIL_SIZE x THUMBNAIL_SIZE box.
+pub const THUMBNAIL_SIZE: u32 = 320;
+pub const THUMBNAIL_MIME_TYPE: &str = "image/jpeg";
+const THUMBNAIL_QUALITY: u8 = 80;
+// the avatars are square, largest first, each size is made from the previous one.
+pub const AVATAR_SIZES: [u32; 3] = [512, 256, 64];
+pub const AVATAR_MIME_TYPE: &str = "image/jpeg";
+const BLURHASH_COMPONENTS: (u32, u32) = (4, 3);
+const GPS_IFD_TAG: u16 = 0x8825;
+// a small file can declare huge dimensions, the decoded pixels are limited.
+const MAX_DECODED_DIMENSION: u32 = 12_000;
+const MAX_DECODED_SIZE: u64 = 256 * 1024 * 1024;
+
+
+// the images the decoder reads, the others are stored as plain files.
+pub fn is_supported_image(mime_type: &str) -> bool {
+    return matches!(mime_type, "image/jpeg" | "image/png" | "image/gif" | "image/webp");
+}
+
+pub struct ImageInfo {
+    // after the EXIF orientation is applied.
+    pub width: i32,
+    pub height: i32,
+    pub blurhash: String,
+    pub thumbnail: Vec<u8>,
+}
+
+// the EXIF of the image can not be read, so it can not be told free of GPS tags.
+pub struct UnreadableExif;
+
+// removes the GPS tags from the EXIF of JPEG, PNG and WebP images,
+// the rest of the metadata (e.g. the orientation) is kept.
+// returns `None` when there was nothing to strip, the image must not be kept on `Err`.
+pub fn strip_gps(data: &[u8]) -> Result<Option<Vec<u8>>, UnreadableExif> {
+    let mut image = match DynImage::from_bytes(Bytes::copy_from_slice(data)) {
+        Ok(Some(image)) => image,
+        // not an image img_parts knows, it has no EXIF we could strip either.
+        Ok(None) => return Ok(None),
+        Err(_) => return Err(UnreadableExif)
+    };
+    let Some(exif) = image.exif() else {
+        return Ok(None);
+    };
+    let mut exif = exif.to_vec();
+    if !scrub_gps_ifd(&mut exif)? {
+        return Ok(None);
+    }
+    // the segment or chunk is rewritten, with the checksum of the png chunk.
+    image.set_exif(Some(Bytes::from(exif)));
+    return Ok(Some(image.encoder().bytes().to_vec()));
+}
+
+// empties the GPS IFD of the TIFF structure in place, so no offset has to move.
+// returns `false` when there is no GPS IFD.
+fn scrub_gps_ifd(exif: &mut [u8]) -> Result<bool, UnreadableExif> {
+    let little_endian = match exif.get(0..4) {
+        Some(b"II*\0") => true,
+        Some(b"MM\0*") => false,
+        _ => return Err(UnreadableExif)
+    };
+    let read_u16 = |exif: &[u8], at: usize| -> Option<u16> {
+        let bytes: [u8; 2] = exif.get(at..at + 2)?.try_into().ok()?;
+        return Some(if little_endian { u16::from_le_bytes(bytes) } else { u16::from_be_bytes(bytes) });
+    };
+    let read_u32 = |exif: &[u8], at: usize| -> Option<usize> {
+        let bytes: [u8; 4] = exif.get(at..at + 4)?.try_into().ok()?;
+        let value = if little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) };
+        return Some(value as usize);
+    };
+    let Some(ifd0) = read_u32(exif, 4) else {
+        return Err(UnreadableExif);
+    };
+    let Some(ifd0_count) = read_u16(exif, ifd0) else {
+        return Err(UnreadableExif);
+    };
+    if ifd0 + 2 + ifd0_count as usize * 12 > exif.len() {
+        return Err(UnreadableExif);
+    }
+    let gps_entry = (0..ifd0_count as usize)
+        .map(|i| ifd0 + 2 + i * 12)
+        .find(|entry| read_u16(exif, *entry) == Some(GPS_IFD_TAG));
+    let Some(gps_entry) = gps_entry else {
+        return Ok(false);
+    };
+    let Some(gps_ifd) = read_u32(exif, gps_entry + 8) else {
+        return Err(UnreadableExif);
+    };
+    let Some(gps_count) = read_u16(exif, gps_ifd) else {
+        return Err(UnreadableExif);
+    };
+    let entries_end = gps_ifd + 2 + gps_count as usize * 12;
+    if entries_end + 4 > exif.len() {
+        return Err(UnreadableExif);
+    }
+    for i in 0..gps_count as usize {
+        let entry = gps_ifd + 2 + i * 12;
+        let value_type = read_u16(exif, entry + 2).unwrap_or(0);
+        let count = read_u32(exif, entry + 4).unwrap_or(0);
+        let size = match value_type {
+            1 | 2 | 6 | 7 => 1,
+            3 | 8 => 2,
+            4 | 9 | 11 => 4,
+            5 | 10 | 12 => 8,
+            _ => 0
+        } * count;
+        // the values that do not fit in the entry are stored elsewhere.
+        if size > 4 && let Some(offset) = read_u32(exif, entry + 8) &&
+            let Some(value) = exif.get_mut(offset..offset.saturating_add(size)) {
+            value.fill(0);
+        }
+    }
+    // no entries and no next IFD.
+    exif[gps_ifd + 2..entries_end + 4].fill(0);
+    exif[gps_ifd..gps_ifd + 2].fill(0);
+    return Ok(true);
+}
+
+// decodes the image to measure it and to make its thumbnail and blurhash,
+// returns `None` for the files the decoder does not support.
+pub fn inspect_image(data: &[u8]) -> Option<ImageInfo> {
+    let image = decode(data)?;
+    let thumbnail = if image.width() > THUMBNAIL_SIZE || image.height() > THUMBNAIL_SIZE {
+        image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+    } else {
+        image.clone()
+    };
+    let rgba = thumbnail.to_rgba8();
+    let blurhash = blurhash::encode(
+        BLURHASH_COMPONENTS.0,
+        BLURHASH_COMPONENTS.1,
+        rgba.width(),
+        rgba.height(),
+        rgba.as_raw()
+    ).ok()?;
+    return Some(ImageInfo {
+        width: image.width() as i32,
+        height: image.height() as i32,
+        blurhash,
+        thumbnail: encode_jpeg(&thumbnail)?
+    });
+}
+
+// crops the center square of the image and encodes it in the AVATAR_SIZES,
+// the sizes larger than the image are skipped instead of upscaled,
+// except the smallest that keeps the size of the image.
+// returns `None` for the files the decoder does not support.
+pub fn make_avatars(data: &[u8]) -> Option<Vec<(u32, Vec<u8>)>> {
+    let mut image = decode(data)?;
+    let side = image.width().min(image.height());
+    let smallest = AVATAR_SIZES[AVATAR_SIZES.len() - 1];
+    let mut avatars = Vec::with_capacity(AVATAR_SIZES.len());
+    for size in AVATAR_SIZES {
+        if size > side && size != smallest {
+            continue;
+        }
+        let side = size.min(side);
+        image = image.resize_to_fill(side, side, FilterType::CatmullRom);
+        avatars.push((size, encode_jpeg(&image)?));
+    }
+    return Some(avatars);
+}
+
+// the EXIF orientation is applied, the images larger than the limits are not decoded.
+fn decode(data: &[u8]) -> Option<DynamicImage> {
+    let mut reader = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .ok()?;
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DECODED_DIMENSION);
+    limits.max_image_height = Some(MAX_DECODED_DIMENSION);
+    limits.max_alloc = Some(MAX_DECODED_SIZE);
+    reader.limits(limits);
+    let mut decoder = reader.into_decoder().ok()?;
+    // not every decoder checks `max_alloc` itself.
+    if decoder.total_bytes() > MAX_DECODED_SIZE {
+        return None;
+    }
+    let orientation = decoder.orientation().ok()?;
+    let mut image = DynamicImage::from_decoder(decoder).ok()?;
+    image.apply_orientation(orientation);
+    return Some(image);
+}
+
+// jpeg has no alpha, the transparent pixels are put on white.
+fn encode_jpeg(image: &DynamicImage) -> Option<Vec<u8>> {
+    let rgba = image.to_rgba8();
+    let rgb = RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        let blend = |c: u8| ((c as u32 * a as u32 + 255 * (255 - a as u32)) / 255) as u8;
+        return image::Rgb([blend(r), blend(g), blend(b)]);
+    });
+    let mut data = Vec::new();
+    JpegEncoder::new_with_quality(&mut data, THUMBNAIL_QUALITY)
+        .encode_image(&rgb)
+        .ok()?;
+    return Some(data);
+}
+
+#[cfg(test)]
+mod tests {
+    use image::metadata::Orientation;
+
+    use super::*;
+
+    const ORIENTATION_TAG: u16 = 0x0112;
+    // where the GPS IFD and the latitude it points to are in `exif()`.
+    const GPS_IFD: usize = 38;
+    const LATITUDE: usize = 68;
+
+    // a little endian TIFF with the orientation and a GPS IFD,
+    // the latitude is a rational triple stored out of its entry.
+    fn exif() -> Vec<u8> {
+        let mut exif = b"II*\0".to_vec();
+        exif.extend_from_slice(&8u32.to_le_bytes());
+        let entry = |exif: &mut Vec<u8>, tag: u16, value_type: u16, count: u32, value: [u8; 4]| {
+            exif.extend_from_slice(&tag.to_le_bytes());
+            exif.extend_from_slice(&value_type.to_le_bytes());
+            exif.extend_from_slice(&count.to_le_bytes());
+            exif.extend_from_slice(&value);
+        };
+        exif.extend_from_slice(&2u16.to_le_bytes());
+        entry(&mut exif, ORIENTATION_TAG, 3, 1, [6, 0, 0, 0]);
+        entry(&mut exif, GPS_IFD_TAG, 4, 1, (GPS_IFD as u32).to_le_bytes());
+        exif.extend_from_slice(&0u32.to_le_bytes());
+        assert_eq!(exif.len(), GPS_IFD);
+        exif.extend_from_slice(&2u16.to_le_bytes());
+        entry(&mut exif, 0x0001, 2, 2, *b"N\0\0\0");
+        entry(&mut exif, 0x0002, 5, 3, (LATITUDE as u32).to_le_bytes());
+        exif.extend_from_slice(&0u32.to_le_bytes());
+        assert_eq!(exif.len(), LATITUDE);
+        for (numerator, denominator) in [(48u32, 1u32), (51, 1), (2999, 100)] {
+            exif.extend_from_slice(&numerator.to_le_bytes());
+            exif.extend_from_slice(&denominator.to_le_bytes());
+        }
+        return exif;
+    }
+
+    fn jpeg(exif: Option<Vec<u8>>) -> Vec<u8> {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(8, 4, image::Rgb([200, 10, 10])));
+        let data = encode_jpeg(&image).unwrap();
+        let Some(exif) = exif else {
+            return data;
+        };
+        let mut image = DynImage::from_bytes(Bytes::from(data)).unwrap().unwrap();
+        image.set_exif(Some(Bytes::from(exif)));
+        return image.encoder().bytes().to_vec();
+    }
+
+    fn exif_of(data: &[u8]) -> Vec<u8> {
+        let image = DynImage::from_bytes(Bytes::copy_from_slice(data)).unwrap().unwrap();
+        return image.exif().unwrap().to_vec();
+    }
+
+    #[test]
+    fn strips_the_gps_tags() {
+        let stripped = strip_gps(&jpeg(Some(exif()))).ok().flatten().unwrap();
+        let exif = exif_of(&stripped);
+        assert_eq!(exif.len(), self::exif().len());
+        // no GPS entries, and the latitude is zeroed.
+        assert_eq!(&exif[GPS_IFD..GPS_IFD + 2], &[0, 0]);
+        assert!(exif[GPS_IFD..].iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn keeps_the_orientation() {
+        let stripped = strip_gps(&jpeg(Some(exif()))).ok().flatten().unwrap();
+        assert_eq!(&exif_of(&stripped)[10..12], &ORIENTATION_TAG.to_le_bytes());
+        let mut decoder = ImageReader::new(Cursor::new(&stripped))
+            .with_guessed_format()
+            .unwrap()
+            .into_decoder()
+            .unwrap();
+        assert_eq!(decoder.orientation().unwrap(), Orientation::Rotate90);
+        assert!(inspect_image(&stripped).is_some());
+    }
+
+    #[test]
+    fn rejects_unreadable_exif() {
+        // cut in the middle of the GPS IFD.
+        let mut truncated = exif();
+        truncated.truncate(GPS_IFD + 10);
+        assert!(strip_gps(&jpeg(Some(truncated))).is_err());
+        // the GPS IFD points past the end.
+        let mut outside = exif();
+        outside[30..34].copy_from_slice(&1000u32.to_le_bytes());
+        assert!(strip_gps(&jpeg(Some(outside))).is_err());
+        // IFD0 claims more entries than there are.
+        let mut counted = exif();
+        counted[8..10].copy_from_slice(&u16::MAX.to_le_bytes());
+        assert!(strip_gps(&jpeg(Some(counted))).is_err());
+        // not a TIFF header.
+        let mut header = exif();
+        header[0..4].copy_from_slice(b"XX*\0");
+        assert!(strip_gps(&jpeg(Some(header))).is_err());
+        // the file ends in the EXIF segment.
+        let data = jpeg(Some(exif()));
+        assert!(strip_gps(&data[..40]).is_err());
+    }
+
+    #[test]
+    fn keeps_the_files_without_exif() {
+        assert!(matches!(strip_gps(&jpeg(None)), Ok(None)));
+        let mut exif = exif();
+        // IFD0 without the GPS IFD.
+        exif[8..10].copy_from_slice(&1u16.to_le_bytes());
+        assert!(matches!(strip_gps(&jpeg(Some(exif))), Ok(None)));
+        assert!(matches!(strip_gps(b"plain text"), Ok(None)));
+    }
+}
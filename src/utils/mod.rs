@@ -1,6 +1,10 @@
 use axum::http::{HeaderMap, HeaderValue};
 use cookie::Cookie;
 
+pub mod media;
+pub mod patch;
+pub mod timestamp;
+
 fn build_header(cookie: String) -> HeaderMap {
     let mut header = HeaderMap::new();
     header.insert(
@@ -26,4 +30,12 @@ pub fn empty_auth_header() -> HeaderMap {
         .http_only(true)
         .max_age(cookie::time::Duration::seconds(0)).to_string();
     return build_header(cookie);
-}
\ No newline at end of file
+}
+
+// a comma separated list of etags or `*`, compared weakly.
+pub fn etag_matches(list: &str, etag: &str) -> bool {
+    return list
+        .split(',')
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag);
+}
@@ -0,0 +1,58 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use validator::ValidateLength;
+
+
+// a field of a PATCH body that can be cleared: missing, sent as null or sent with a value.
+// the field needs `#[serde(default)]` so that a missing one is `Absent`.
+#[derive(Default, Clone, PartialEq)]
+pub enum Patch<T> {
+    #[default]
+    Absent,
+    Null,
+    Value(T),
+}
+
+impl<T> Patch<T> {
+    pub fn is_absent(&self) -> bool {
+        return matches!(self, Patch::Absent);
+    }
+
+    // `None` when absent, otherwise the new value of the column.
+    pub fn into_option(self) -> Option<Option<T>> {
+        match self {
+            Patch::Absent => return None,
+            Patch::Null => return Some(None),
+            Patch::Value(value) => return Some(Some(value))
+        }
+    }
+}
+
+// only called for the fields that are present.
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Patch<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match Option::<T>::deserialize(deserializer)? {
+            Some(value) => return Ok(Patch::Value(value)),
+            None => return Ok(Patch::Null)
+        }
+    }
+}
+
+// the validation errors echo the rejected value.
+impl<T: Serialize> Serialize for Patch<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Patch::Value(value) => return value.serialize(serializer),
+            _ => return serializer.serialize_none()
+        }
+    }
+}
+
+// lets `#[validate(length(...))]` check the value, absent and null are valid.
+impl<T: ValidateLength<u64>> ValidateLength<u64> for Patch<T> {
+    fn length(&self) -> Option<u64> {
+        match self {
+            Patch::Value(value) => return value.length(),
+            _ => return None
+        }
+    }
+}
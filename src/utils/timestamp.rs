@@ -0,0 +1,29 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serializer;
+
+
+// all the timestamps leave the api as RFC 3339 in UTC with millisecond precision,
+// e.g. "2025-05-21T06:18:36.123Z".
+pub fn serialize<S: Serializer>(
+    timestamp: &DateTime<Utc>,
+    serializer: S
+) -> Result<S::Ok, S::Error> {
+    return serializer.serialize_str(
+        &timestamp.to_rfc3339_opts(SecondsFormat::Millis, true)
+    );
+}
+
+pub fn serialize_option<S: Serializer>(
+    timestamp: &Option<DateTime<Utc>>,
+    serializer: S
+) -> Result<S::Ok, S::Error> {
+    match timestamp {
+        Some(timestamp) => return serialize(timestamp, serializer),
+        None => return serializer.serialize_none()
+    }
+}
+
+// the IMF-fixdate of the http headers, e.g. "Wed, 21 May 2025 06:18:36 GMT".
+pub fn http_date(timestamp: &DateTime<Utc>) -> String {
+    return timestamp.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+}